@@ -1,5 +1,11 @@
-use crate::database::Database;
+//! Running sums over ranges of the index.
 
+use crate::database::CountSource;
+
+/// Accumulator keeps the sum of the counts in a [gte, lt) range of the index
+/// and moves the range to the next one by only visiting the counts that enter or
+/// leave it, so walking ranges in increasing order visits every count about
+/// once. Ranges must lie within the database or sum_to panics.
 #[derive(Default)]
 pub struct Accumulator {
   gte: usize,
@@ -13,7 +19,7 @@ impl Accumulator {
   //
   // TODO: it is possible to do less work. Say for the case where the acc is at [0,5)
   // and then is requested to move to [9,25).
-  pub fn sum_to(&mut self, mmap: &Database, gte: usize, lt: usize) -> u64 {
+  pub fn sum_to<C: CountSource>(&mut self, mmap: &C, gte: usize, lt: usize) -> u64 {
     use std::cmp::Ordering;
 
     if gte > self.lt || lt < self.gte {
@@ -30,9 +36,9 @@ impl Accumulator {
           self.lt = self.lt + 1;
         },
         Ordering::Greater => {
+          self.lt = self.lt - 1;
           let c = mmap[self.lt];
           self.sum = self.sum - c as i64;
-          self.lt = self.lt - 1;
         },
         Ordering::Equal => {
           break
@@ -47,9 +53,9 @@ impl Accumulator {
           self.gte = self.gte + 1;
         },
         Ordering::Greater => {
+          self.gte = self.gte - 1;
           let c = mmap[self.gte];
           self.sum = self.sum + c as i64;
-          self.gte = self.gte - 1;
         },
         Ordering::Equal => {
           break
@@ -62,6 +68,7 @@ impl Accumulator {
 
 #[cfg(test)]
 mod test {
+  use crate::database::Database;
   use anyhow::Result;
 
   #[test]
@@ -77,4 +84,15 @@ mod test {
     println!("count = {c}");
     Ok(())
   }
+
+  #[test]
+  fn test_shrink() {
+    use crate::accumulator::*;
+    use crate::database::temp_database;
+    let m = temp_database(1, &[("a", 1), ("c", 2), ("g", 4), ("t", 8)]);
+    let mut a = Accumulator::default();
+    assert_eq!(15, a.sum_to(&m, 0, 4));
+    assert_eq!(6, a.sum_to(&m, 1, 3));
+    assert_eq!(3, a.sum_to(&m, 0, 2));
+  }
 }
\ No newline at end of file
@@ -0,0 +1,367 @@
+//! Alphabets map the letters of a sequence onto the symbols an index counts.
+
+use crate::error::{Error, Result};
+use crate::kmer::Kmer;
+use std::fmt;
+use std::str::FromStr;
+
+const NONE: u8 = u8::MAX;
+
+/// Alphabet is an ordered set of symbols, each standing for one or more
+/// letters. An index over an alphabet of radix r holds r^l sequences of every
+/// length l, ordered by the position of their symbols, first symbol most
+/// significant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alphabet {
+    name: String,
+    // groups holds the lowercase letters of every symbol, the first letter
+    // naming the symbol.
+    groups: Vec<String>,
+    codes: [u8; 256],
+}
+
+/// base_index returns the index of the first sequence of length seqlen in an
+/// index over an alphabet of radix symbols.
+pub fn base_index(radix: usize, seqlen: usize) -> usize {
+    (1..seqlen).fold(0, |acc, i| acc + ring_len(radix, i))
+}
+
+/// ring_len returns the number of sequences of length seqlen.
+pub fn ring_len(radix: usize, seqlen: usize) -> usize {
+    radix.pow(seqlen as u32)
+}
+
+impl Alphabet {
+    /// new builds an alphabet from the letters of each of its symbols. Letters
+    /// match in either case and may not be shared by two symbols.
+    pub fn new(name: &str, groups: &[&str]) -> Result<Alphabet> {
+        if groups.len() < 2 || groups.len() >= NONE as usize {
+            return Err(Error::InvalidAlphabet(format!(
+                "{} has {} symbols but needs 2..{}",
+                name,
+                groups.len(),
+                NONE
+            )));
+        }
+        let mut codes = [NONE; 256];
+        for (code, group) in groups.iter().enumerate() {
+            if group.is_empty() {
+                return Err(Error::InvalidAlphabet(format!(
+                    "{} has an empty symbol",
+                    name
+                )));
+            }
+            for l in group.bytes() {
+                if !l.is_ascii_alphabetic() {
+                    return Err(Error::InvalidAlphabet(format!(
+                        "{} holds {:?} which is not a letter",
+                        name, l as char
+                    )));
+                }
+                for l in [l.to_ascii_lowercase(), l.to_ascii_uppercase()] {
+                    if codes[l as usize] != NONE {
+                        return Err(Error::InvalidAlphabet(format!(
+                            "{} maps {:?} to two symbols",
+                            name, l as char
+                        )));
+                    }
+                    codes[l as usize] = code as u8;
+                }
+            }
+        }
+        Ok(Alphabet {
+            name: name.to_string(),
+            groups: groups.iter().map(|g| g.to_ascii_lowercase()).collect(),
+            codes,
+        })
+    }
+
+    pub fn dna() -> Alphabet {
+        Alphabet::new("dna", &["a", "c", "g", "t"]).expect("dna is valid")
+    }
+
+    pub fn rna() -> Alphabet {
+        Alphabet::new("rna", &["a", "c", "g", "u"]).expect("rna is valid")
+    }
+
+    /// protein holds the 20 standard amino acids.
+    pub fn protein() -> Alphabet {
+        let groups: Vec<String> = "acdefghiklmnpqrstvwy"
+            .chars()
+            .map(|l| l.to_string())
+            .collect();
+        let groups: Vec<&str> = groups.iter().map(|g| g.as_str()).collect();
+        Alphabet::new("protein", &groups).expect("protein is valid")
+    }
+
+    /// purine_pyrimidine reduces nucleotides to r for a and g and y for c, t
+    /// and u.
+    pub fn purine_pyrimidine() -> Alphabet {
+        Alphabet::new("purine-pyrimidine", &["rag", "yctu"]).expect("purine-pyrimidine is valid")
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn radix(&self) -> usize {
+        self.groups.len()
+    }
+
+    /// symbols returns the letter naming every symbol, in index order.
+    pub fn symbols(&self) -> String {
+        self.groups
+            .iter()
+            .filter_map(|g| g.chars().next())
+            .collect()
+    }
+
+    /// spec returns the letters of every symbol separated by commas, which
+    /// parses back into the same mapping.
+    pub fn spec(&self) -> String {
+        self.groups.join(",")
+    }
+
+    pub fn is_dna(&self) -> bool {
+        self.groups == ["a", "c", "g", "t"]
+    }
+
+    /// check_dna fails unless the alphabet is dna, for the parts of hustar
+    /// that only understand nucleotides.
+    pub fn check_dna(&self, backend: &'static str) -> Result<()> {
+        if !self.is_dna() {
+            return Err(Error::UnsupportedAlphabet {
+                alphabet: self.name.clone(),
+                backend,
+            });
+        }
+        Ok(())
+    }
+
+    /// code returns the position of the symbol holding letter l.
+    pub fn code(&self, l: u8) -> Option<usize> {
+        match self.codes[l as usize] {
+            NONE => None,
+            code => Some(code as usize),
+        }
+    }
+
+    pub fn base_index(&self, seqlen: usize) -> usize {
+        base_index(self.radix(), seqlen)
+    }
+
+    pub fn ring_len(&self, seqlen: usize) -> usize {
+        ring_len(self.radix(), seqlen)
+    }
+
+    /// max_index_len is the longest sequence whose position in a dense index,
+    /// which holds every shorter sequence before it, still fits a usize.
+    pub fn max_index_len(&self) -> usize {
+        let radix = self.radix();
+        let (mut len, mut ring, mut end) = (0_usize, 1_usize, 0_usize);
+        loop {
+            let next = ring
+                .checked_mul(radix)
+                .and_then(|ring| end.checked_add(ring).map(|end| (ring, end)));
+            match next {
+                Some((r, e)) => {
+                    ring = r;
+                    end = e;
+                    len += 1;
+                }
+                // the index of the last sequence of len is end - 1.
+                None => return len,
+            }
+        }
+    }
+
+    /// check_index_len fails for lengths a dense index cannot hold.
+    pub fn check_index_len(&self, seqlen: usize) -> Result<()> {
+        let max = self.max_index_len();
+        if seqlen > max {
+            return Err(Error::UnsupportedSeqlen {
+                seqlen,
+                max,
+                backend: "dense index",
+            });
+        }
+        Ok(())
+    }
+
+    /// seq_to_index returns the position of seq in a dense index.
+    pub fn seq_to_index(&self, seq: &str) -> Result<usize> {
+        // dna keeps the errors of Kmer, which it packs 2 bits per nucleotide.
+        if self.is_dna() {
+            return Kmer::from_seq(seq)?.try_index();
+        }
+        self.check_index_len(seq.len())?;
+        let mut addr = 0;
+        for l in seq.chars() {
+            let code = u8::try_from(l)
+                .ok()
+                .and_then(|l| self.code(l))
+                .ok_or_else(|| Error::InvalidSymbol {
+                    letter: l,
+                    seq: seq.to_string(),
+                    alphabet: self.name.clone(),
+                })?;
+            addr = addr * self.radix() + code;
+        }
+        Ok(self.base_index(seq.len()) + addr)
+    }
+
+    /// index_to_seq returns the sequence stored at index of a dense index.
+    pub fn index_to_seq(&self, index: usize) -> String {
+        let mut len = 1;
+        while len < self.max_index_len() && index >= self.base_index(len + 1) {
+            len += 1;
+        }
+        let mut addr = index - self.base_index(len);
+        let symbols: Vec<char> = self.symbols().chars().collect();
+        let mut seq = vec![' '; len];
+        for l in seq.iter_mut().rev() {
+            *l = symbols[addr % self.radix()];
+            addr /= self.radix();
+        }
+        seq.into_iter().collect()
+    }
+
+    /// windows iterates the start and index of every window of length len in
+    /// seq, skipping windows holding letters outside of the alphabet.
+    pub fn windows<'a>(&'a self, seq: &'a [u8], len: usize) -> Windows<'a> {
+        Windows {
+            alphabet: self,
+            seq,
+            len,
+            ring: self.ring_len(len) as u128,
+            base: self.base_index(len),
+            pos: 0,
+            valid: 0,
+            addr: 0,
+        }
+    }
+}
+
+impl FromStr for Alphabet {
+    type Err = Error;
+
+    /// from_str parses the name of a predefined alphabet, or the comma
+    /// separated letters of every symbol, such as "ag,ct".
+    fn from_str(s: &str) -> Result<Alphabet> {
+        match s {
+            "dna" => Ok(Alphabet::dna()),
+            "rna" => Ok(Alphabet::rna()),
+            "protein" => Ok(Alphabet::protein()),
+            "purine-pyrimidine" | "ry" => Ok(Alphabet::purine_pyrimidine()),
+            spec => {
+                let groups: Vec<&str> = spec.split(',').collect();
+                Alphabet::new(spec, &groups)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Alphabet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+/// Windows is the iterator returned by Alphabet::windows.
+pub struct Windows<'a> {
+    alphabet: &'a Alphabet,
+    seq: &'a [u8],
+    len: usize,
+    ring: u128,
+    base: usize,
+    pos: usize,
+    // valid counts the letters of the alphabet in a row ending before pos.
+    valid: usize,
+    addr: u128,
+}
+
+impl<'a> Iterator for Windows<'a> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pos < self.seq.len() {
+            let l = self.seq[self.pos];
+            self.pos += 1;
+            match self.alphabet.code(l) {
+                Some(code) => {
+                    let radix = self.alphabet.radix() as u128;
+                    self.addr = (self.addr * radix + code as u128) % self.ring;
+                    self.valid += 1;
+                }
+                None => {
+                    self.valid = 0;
+                    continue;
+                }
+            }
+            if self.len > 0 && self.valid >= self.len {
+                return Some((self.pos - self.len, self.base + self.addr as usize));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_alphabet() -> Result<()> {
+        let dna = Alphabet::dna();
+        assert_eq!(crate::kmer::MAX_INDEX_LEN, dna.max_index_len());
+        assert_eq!(4 + 16 + 64 + 256, dna.base_index(5));
+        assert_eq!(dna, "dna".parse()?);
+
+        let protein: Alphabet = "protein".parse()?;
+        assert_eq!(20, protein.radix());
+        assert_eq!(20 + 400, protein.base_index(3));
+        assert_eq!(20 + 1, protein.seq_to_index("AC")?);
+        assert_eq!("ac", protein.index_to_seq(21));
+        assert!(matches!(
+            protein.seq_to_index("ab"),
+            Err(Error::InvalidSymbol { letter: 'b', .. })
+        ));
+        assert!(protein.check_index_len(protein.max_index_len()).is_ok());
+        assert!(protein
+            .check_index_len(protein.max_index_len() + 1)
+            .is_err());
+
+        let ry = Alphabet::purine_pyrimidine();
+        assert_eq!("ry", ry.symbols());
+        assert_eq!(ry.seq_to_index("ry")?, ry.seq_to_index("gU")?);
+        for index in 0..ry.base_index(5) {
+            assert_eq!(index, ry.seq_to_index(&ry.index_to_seq(index))?);
+        }
+
+        let custom: Alphabet = "ag,ct".parse()?;
+        assert_eq!("ag,ct", custom.spec());
+        assert_eq!(
+            custom,
+            Alphabet::new("ag,ct", &custom.spec().split(',').collect::<Vec<_>>())?
+        );
+        assert!("ag,ga".parse::<Alphabet>().is_err());
+        assert!("acgt".parse::<Alphabet>().is_err());
+        assert!(custom.check_dna("fcgr").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_windows() {
+        let rna = Alphabet::rna();
+        let found: Vec<(usize, String)> = rna
+            .windows(b"ACxgUUa", 2)
+            .map(|(start, index)| (start, rna.index_to_seq(index)))
+            .collect();
+        let expected = vec![(0, "ac"), (3, "gu"), (4, "uu"), (5, "ua")];
+        let expected: Vec<(usize, String)> = expected
+            .into_iter()
+            .map(|(s, q)| (s, q.to_string()))
+            .collect();
+        assert_eq!(expected, found);
+    }
+}
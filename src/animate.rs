@@ -0,0 +1,136 @@
+// animate renders a sequence of stars that share one normalization so that
+// frames can be compared with each other. Frames either grow the star from
+// seqlen 1 to a max seqlen, or show one index per frame, e.g. one index built
+// per chromosome.
+
+use crate::database::Database;
+use crate::error::{image_error, io_error, Error, Result};
+use crate::layout::Radial;
+use crate::render::{density_image, render, ring_maxes};
+use crate::traverse::Sector;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, DynamicImage, Frame, GrayImage};
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+use tracing::info;
+
+fn frame(m: &Database, side: usize, seqlen: usize, ring_maxes: &[u32]) -> GrayImage {
+    let layout = Radial {
+        sector: Sector {
+            radix: m.alphabet().radix(),
+            ..Sector::full(seqlen)
+        },
+        samples: 1,
+    };
+    let (pixels, counts) = render(m, &layout, side, side, seqlen);
+    density_image(&pixels, &counts, ring_maxes)
+}
+
+// seqlen_frames renders the star of every seqlen from 1 to max_seqlen.
+pub fn seqlen_frames(m: &Database, side: usize, max_seqlen: usize) -> Result<Vec<GrayImage>> {
+    let ring_maxes = ring_maxes(m, max_seqlen)?;
+    let mut frames = Vec::new();
+    for seqlen in 1..=max_seqlen {
+        info!("rendering frame seqlen={}", seqlen);
+        frames.push(frame(m, side, seqlen, &ring_maxes));
+    }
+    Ok(frames)
+}
+
+// index_frames renders the star of every index, normalized by the largest count
+// of every ring across all of them.
+pub fn index_frames(ms: &[Database], side: usize, seqlen: usize) -> Result<Vec<GrayImage>> {
+    let mut shared = vec![0; seqlen + 1];
+    for m in ms {
+        for (shared, max) in shared.iter_mut().zip(ring_maxes(m, seqlen)?) {
+            *shared = (*shared).max(max);
+        }
+    }
+    let mut frames = Vec::new();
+    for (i, m) in ms.iter().enumerate() {
+        info!("rendering frame of index {} of {}", i + 1, ms.len());
+        frames.push(frame(m, side, seqlen, &shared));
+    }
+    Ok(frames)
+}
+
+pub fn write_gif<P: AsRef<Path>>(path: P, frames: &[GrayImage], delay_ms: u32) -> Result<()> {
+    let path = path.as_ref();
+    let file = File::create(path).map_err(io_error(path))?;
+    let mut encoder = GifEncoder::new(BufWriter::new(file));
+    encoder
+        .set_repeat(Repeat::Infinite)
+        .map_err(image_error(path))?;
+    for img in frames {
+        let rgba = DynamicImage::ImageLuma8(img.clone()).into_rgba8();
+        let delay = Delay::from_numer_denom_ms(delay_ms, 1);
+        encoder
+            .encode_frame(Frame::from_parts(rgba, 0, 0, delay))
+            .map_err(image_error(path))?;
+    }
+    Ok(())
+}
+
+// write_frames writes every frame as frame_<n>.png into dir.
+pub fn write_frames<P: AsRef<Path>>(dir: P, frames: &[GrayImage]) -> Result<()> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir).map_err(io_error(dir))?;
+    for (i, img) in frames.iter().enumerate() {
+        let path = dir.join(format!("frame_{:04}.png", i + 1));
+        img.save(&path).map_err(image_error(path))?;
+    }
+    Ok(())
+}
+
+// write writes a gif if output ends in .gif and a directory of frames otherwise.
+pub fn write<P: AsRef<Path>>(output: P, frames: &[GrayImage], delay_ms: u32) -> Result<()> {
+    let output = output.as_ref();
+    if frames.is_empty() {
+        return Err(Error::InvalidOutput(format!(
+            "no frames to write to {:?}",
+            output
+        )));
+    }
+    match output.extension().and_then(|e| e.to_str()) {
+        Some("gif") => write_gif(output, frames, delay_ms),
+        _ => write_frames(output, frames),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::temp_database;
+    use image::AnimationDecoder;
+
+    fn database(count: u32) -> Database {
+        temp_database(2, &[("a", count), ("ag", count)])
+    }
+
+    #[test]
+    fn test_seqlen_frames() -> Result<()> {
+        let m = database(2);
+        let frames = seqlen_frames(&m, 16, 2)?;
+        assert_eq!(2, frames.len());
+        assert_eq!((16, 16), frames[1].dimensions());
+
+        let gif = tempfile::Builder::new().suffix(".gif").tempfile().unwrap();
+        write(gif.path(), &frames, 100)?;
+        let gif = File::open(gif.path()).unwrap();
+        let decoder = image::codecs::gif::GifDecoder::new(gif).unwrap();
+        assert_eq!(2, decoder.into_frames().count());
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_frames() -> Result<()> {
+        let ms = vec![database(1), database(4)];
+        let frames = index_frames(&ms, 16, 2)?;
+        let low = frames[0].pixels().map(|p| p.0[0]).max();
+        let high = frames[1].pixels().map(|p| p.0[0]).max();
+        assert_eq!(Some(255), high);
+        assert!(low < high);
+        Ok(())
+    }
+}
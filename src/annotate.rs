@@ -0,0 +1,454 @@
+// annotate draws ring boundaries, first symbol labels, a colorbar and a
+// title onto rendered stars, both raster and svg, so figures can be used
+// without manual editing. Text is drawn with a small built in bitmap font.
+
+use crate::error::{Error, Result};
+use crate::traverse::Sector;
+use image::{ImageBuffer, Rgb, RgbImage};
+use std::f64::consts::PI;
+use std::io::Write;
+
+const RING: Rgb<u8> = Rgb([255, 160, 0]);
+const TEXT: Rgb<u8> = Rgb([255, 255, 255]);
+const TICKS: usize = 5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Legend {
+    pub title: String,
+    // sector is the part of the star shown. Layouts that are not radial have
+    // no rings or quadrants to draw and leave it empty.
+    pub sector: Option<Sector>,
+    // symbols names the symbols of the alphabet of the index in index order.
+    pub symbols: String,
+    // max_count is the count shown at full brightness and count_label names
+    // what is counted.
+    pub max_count: u64,
+    pub count_label: String,
+    // keys name highlighted motifs next to the count label in their color.
+    pub keys: Vec<(String, [u8; 3])>,
+}
+
+// scale grows the font and margins with the image so labels stay readable.
+fn scale(side: u32) -> u32 {
+    (side / 256).max(1)
+}
+
+// margins returns the height of the title band above the star and the colorbar
+// band below it.
+pub fn margins(side: u32) -> (u32, u32) {
+    let s = scale(side);
+    (12 * s, 36 * s)
+}
+
+// ring_boundaries returns the radius of the inner edge of every ring past the
+// innermost as a fraction of the image edge.
+pub fn ring_boundaries(sector: &Sector) -> Vec<f64> {
+    let steps = (sector.max_seqlen - sector.min_seqlen) as f64;
+    (sector.min_seqlen + 1..=sector.max_seqlen)
+        .map(|seqlen| ((seqlen - sector.min_seqlen) as f64 / steps).sqrt())
+        .collect()
+}
+
+// quadrants returns the letter and the visible [gte, lt) image angles of every
+// first symbol that shows up in the sector. With dna these are the quadrants
+// of a, c, g and t.
+pub fn quadrants(sector: &Sector, symbols: &str) -> Vec<(char, f64, f64)> {
+    let to_theta =
+        |f: f64| ((f - sector.gte) / (sector.lt - sector.gte)).clamp(0.0, 1.0) * 2.0 * PI;
+    let n = symbols.len() as f64;
+    symbols
+        .chars()
+        .enumerate()
+        .map(|(i, l)| {
+            let (gte, lt) = (to_theta(i as f64 / n), to_theta((i + 1) as f64 / n));
+            (l.to_ascii_uppercase(), gte, lt)
+        })
+        .filter(|(_, gte, lt)| gte < lt)
+        .collect()
+}
+
+// quadrant_edges returns the image angles at which one first symbol ends and
+// the next begins, leaving out the edges of a partial sector.
+fn quadrant_edges(sector: &Sector, symbols: &str) -> Vec<f64> {
+    quadrants(sector, symbols)
+        .iter()
+        .map(|q| q.1)
+        .filter(|t| *t > 0.0 || sector.lt - sector.gte >= 1.0)
+        .collect()
+}
+
+// tick_counts returns the counts at evenly spaced brightness, undoing the
+// fourth root that maps counts to brightness.
+pub fn tick_counts(max_count: u64) -> Vec<(f64, u64)> {
+    (0..TICKS)
+        .map(|i| {
+            let l = i as f64 / (TICKS - 1) as f64;
+            (l, (max_count as f64 * l.powi(4)).round() as u64)
+        })
+        .collect()
+}
+
+// format_count shortens counts of five or more digits with a unit suffix.
+pub fn format_count(count: u64) -> String {
+    if count < 10_000 {
+        return count.to_string();
+    }
+    let units = [(1e12, "T"), (1e9, "G"), (1e6, "M"), (1e3, "k")];
+    let c = count as f64;
+    for (size, unit) in units {
+        if c >= 10.0 * size {
+            return format!("{:.0}{}", c / size, unit);
+        }
+        if c >= size {
+            return format!("{:.1}{}", c / size, unit);
+        }
+    }
+    count.to_string()
+}
+
+// glyph returns the rows of a 5x7 glyph with the leftmost column in bit 4.
+// Lowercase letters are drawn as uppercase and unknown characters as blanks.
+fn glyph(c: char) -> [u8; 7] {
+    match c.to_ascii_uppercase() {
+        'A' => [0x0e, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11],
+        'B' => [0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e],
+        'C' => [0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e],
+        'D' => [0x1e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x1e],
+        'E' => [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x1f],
+        'F' => [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10],
+        'G' => [0x0e, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0f],
+        'H' => [0x11, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11],
+        'I' => [0x0e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0c],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1f],
+        'M' => [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e],
+        'P' => [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10],
+        'Q' => [0x0e, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0d],
+        'R' => [0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11],
+        'S' => [0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e],
+        'T' => [0x1f, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0a, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0a],
+        'X' => [0x11, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0a, 0x04, 0x04, 0x04],
+        'Z' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1f],
+        '0' => [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e],
+        '1' => [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e],
+        '2' => [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f],
+        '3' => [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e],
+        '4' => [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02],
+        '5' => [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e],
+        '6' => [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e],
+        '7' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e],
+        '9' => [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c],
+        ',' => [0x00, 0x00, 0x00, 0x00, 0x0c, 0x04, 0x08],
+        ':' => [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x0c, 0x00],
+        '-' => [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00],
+        '+' => [0x00, 0x04, 0x04, 0x1f, 0x04, 0x04, 0x00],
+        '=' => [0x00, 0x00, 0x1f, 0x00, 0x1f, 0x00, 0x00],
+        '*' => [0x00, 0x04, 0x15, 0x0e, 0x15, 0x04, 0x00],
+        '/' => [0x01, 0x01, 0x02, 0x04, 0x08, 0x10, 0x10],
+        '_' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1f],
+        '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+        ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+        _ => [0; 7],
+    }
+}
+
+fn text_width(text: &str, scale: u32) -> i64 {
+    (text.chars().count() * 6) as i64 * scale as i64
+}
+
+// draw_text draws text with its top left corner at (x, y), clipping whatever
+// falls outside of the image.
+fn draw_text(img: &mut RgbImage, x: i64, y: i64, scale: u32, text: &str, color: Rgb<u8>) {
+    let s = scale as i64;
+    for (i, c) in text.chars().enumerate() {
+        let left = x + i as i64 * 6 * s;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..5 {
+                if bits & (0x10 >> col) == 0 {
+                    continue;
+                }
+                for dy in 0..s {
+                    for dx in 0..s {
+                        let (px, py) = (left + col * s + dx, y + row as i64 * s + dy);
+                        if px >= 0 && py >= 0 && px < img.width() as i64 && py < img.height() as i64
+                        {
+                            img.put_pixel(px as u32, py as u32, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// at converts a radius in pixels and an image angle into image coordinates,
+// mirrored on both axes like make_points.
+fn at(c: f64, r: f64, t: f64) -> (f64, f64) {
+    (c - r * t.cos(), c - r * t.sin())
+}
+
+// draw_star_overlay draws the ring boundaries, quadrant edges and quadrant
+// letters onto the star whose top left corner is at row top of img.
+fn draw_star_overlay(img: &mut RgbImage, sector: &Sector, symbols: &str, side: u32, top: u32) {
+    let c = side as f64 / 2.0;
+    let radii: Vec<f64> = ring_boundaries(sector).iter().map(|r| r * c).collect();
+    let edges = quadrant_edges(sector, symbols);
+    for py in 0..side {
+        for px in 0..side {
+            let (vx, vy) = (c - (px as f64 + 0.5), c - (py as f64 + 0.5));
+            let d = (vx * vx + vy * vy).sqrt();
+            let on_ring = radii.iter().any(|r| (d - r).abs() < 0.5);
+            let on_edge = edges.iter().any(|t| {
+                let (dx, dy) = (t.cos(), t.sin());
+                vx * dx + vy * dy >= 0.0 && (vx * dy - vy * dx).abs() < 0.5
+            });
+            if on_ring || on_edge {
+                img.put_pixel(px, top + py, RING);
+            }
+        }
+    }
+    let s = scale(side);
+    for (l, gte, lt) in quadrants(sector, symbols) {
+        let (x, y) = at(c, 0.85 * c, (gte + lt) / 2.0);
+        let x = x as i64 - 3 * s as i64;
+        let y = (top as f64 + y) as i64 - 4 * s as i64;
+        draw_text(img, x, y, s, &l.to_string(), RING);
+    }
+}
+
+// annotate returns the star with the legend drawn over it, a title band above
+// and a colorbar band below.
+pub fn annotate(star: &RgbImage, legend: &Legend) -> RgbImage {
+    let side = star.width();
+    let s = scale(side);
+    let (title_height, bar_height) = margins(side);
+    let height = title_height + star.height() + bar_height;
+    let mut img: RgbImage = ImageBuffer::new(side, height);
+    for (x, y, p) in star.enumerate_pixels() {
+        img.put_pixel(x, title_height + y, *p);
+    }
+    if let Some(sector) = &legend.sector {
+        draw_star_overlay(&mut img, sector, &legend.symbols, side, title_height);
+    }
+    draw_text(&mut img, 2 * s as i64, 2 * s as i64, s, &legend.title, TEXT);
+
+    let top = title_height + star.height();
+    let (x0, x1) = (4 * s, side.saturating_sub(4 * s).max(4 * s + 1));
+    for x in x0..x1 {
+        let l = ((x - x0) as f64 / (x1 - 1 - x0).max(1) as f64 * 255.0) as u8;
+        for y in top + 4 * s..top + 12 * s {
+            img.put_pixel(x, y, Rgb([l, l, l]));
+        }
+    }
+    for (l, count) in tick_counts(legend.max_count) {
+        let label = format_count(count);
+        let x = x0 as f64 + l * (x1 - 1 - x0) as f64;
+        let w = text_width(&label, s);
+        let x = (x as i64 - w / 2).clamp(0, (side as i64 - w).max(0));
+        draw_text(&mut img, x, (top + 14 * s) as i64, s, &label, TEXT);
+    }
+    draw_text(
+        &mut img,
+        x0 as i64,
+        (top + 25 * s) as i64,
+        s,
+        &legend.count_label,
+        TEXT,
+    );
+    let mut x = x0 as i64 + text_width(&legend.count_label, s) + 6 * s as i64;
+    for (name, color) in &legend.keys {
+        draw_text(&mut img, x, (top + 25 * s) as i64, s, name, Rgb(*color));
+        x += text_width(name, s) + 6 * s as i64;
+    }
+    img
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// write_svg_overlay writes the legend as svg elements for a star of the given
+// side that is drawn below a title band of margins(side).0.
+pub fn write_svg_overlay<W: Write>(out: &mut W, legend: &Legend, side: u32) -> Result<()> {
+    let s = scale(side) as f64;
+    let (title_height, _) = margins(side);
+    let c = side as f64 / 2.0;
+    let font = format!(r#"font-family="monospace" font-size="{:.0}""#, 9.0 * s);
+    writeln!(
+        out,
+        r#"<text x="{:.0}" y="{:.0}" fill="white" {}>{}</text>"#,
+        2.0 * s,
+        9.0 * s,
+        font,
+        escape(&legend.title)
+    )
+    .map_err(Error::Output)?;
+    if let Some(sector) = &legend.sector {
+        writeln!(
+            out,
+            r#"<g transform="translate(0,{})" stroke="rgb(255,160,0)" fill="none">"#,
+            title_height
+        )
+        .map_err(Error::Output)?;
+        for r in ring_boundaries(sector) {
+            writeln!(out, r#"<circle cx="{c}" cy="{c}" r="{:.3}"/>"#, r * c)
+                .map_err(Error::Output)?;
+        }
+        for t in quadrant_edges(sector, &legend.symbols) {
+            let (x, y) = at(c, c * std::f64::consts::SQRT_2, t);
+            writeln!(
+                out,
+                r#"<line x1="{c}" y1="{c}" x2="{:.3}" y2="{:.3}"/>"#,
+                x, y
+            )
+            .map_err(Error::Output)?;
+        }
+        for (l, gte, lt) in quadrants(sector, &legend.symbols) {
+            let (x, y) = at(c, 0.85 * c, (gte + lt) / 2.0);
+            writeln!(
+                out,
+                r#"<text x="{:.3}" y="{:.3}" fill="rgb(255,160,0)" stroke="none" text-anchor="middle" dominant-baseline="middle" {}>{}</text>"#,
+                x, y, font, l
+            ).map_err(Error::Output)?;
+        }
+        writeln!(out, "</g>").map_err(Error::Output)?;
+    }
+
+    let top = (title_height + side) as f64;
+    let (x0, x1) = (4.0 * s, (side as f64 - 4.0 * s).max(4.0 * s + 1.0));
+    writeln!(
+        out,
+        r#"<defs><linearGradient id="colorbar"><stop offset="0" stop-color="black"/><stop offset="1" stop-color="white"/></linearGradient></defs>"#
+    ).map_err(Error::Output)?;
+    writeln!(
+        out,
+        r#"<rect x="{:.0}" y="{:.0}" width="{:.0}" height="{:.0}" fill="url(#colorbar)"/>"#,
+        x0,
+        top + 4.0 * s,
+        x1 - x0,
+        8.0 * s
+    )
+    .map_err(Error::Output)?;
+    for (l, count) in tick_counts(legend.max_count) {
+        writeln!(
+            out,
+            r#"<text x="{:.3}" y="{:.0}" fill="white" text-anchor="middle" {}>{}</text>"#,
+            x0 + l * (x1 - x0),
+            top + 21.0 * s,
+            font,
+            format_count(count)
+        )
+        .map_err(Error::Output)?;
+    }
+    let mut keys = String::new();
+    for (name, [r, g, b]) in &legend.keys {
+        keys.push_str(&format!(
+            r#" <tspan fill="rgb({},{},{})">{}</tspan>"#,
+            r,
+            g,
+            b,
+            escape(name)
+        ));
+    }
+    writeln!(
+        out,
+        r#"<text x="{:.0}" y="{:.0}" fill="white" {}>{}{}</text>"#,
+        x0,
+        top + 32.0 * s,
+        font,
+        escape(&legend.count_label),
+        keys
+    )
+    .map_err(Error::Output)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::alphabet::Alphabet;
+
+    fn legend(sector: Option<Sector>) -> Legend {
+        Legend {
+            title: "index.bin seqlen=3".to_string(),
+            sector,
+            symbols: "acgt".to_string(),
+            max_count: 160000,
+            count_label: "count".to_string(),
+            keys: vec![("cg".to_string(), [255, 0, 0])],
+        }
+    }
+
+    #[test]
+    fn test_geometry() -> Result<()> {
+        assert_eq!(vec![0.5_f64.sqrt(), 1.0], ring_boundaries(&Sector::full(3)));
+        let q = quadrants(&Sector::full(3), "acgt");
+        assert_eq!(4, q.len());
+        assert_eq!(('C', PI / 2.0, PI), q[1]);
+
+        let dna = Alphabet::dna();
+        let q = quadrants(&Sector::from_prefix(&dna, "gt", 3)?, "acgt");
+        assert_eq!(vec![('G', 0.0, 2.0 * PI)], q);
+        assert_eq!(
+            1,
+            ring_boundaries(&Sector::from_prefix(&dna, "gt", 3)?).len()
+        );
+
+        let q = quadrants(&Sector::full(3), "ry");
+        assert_eq!(vec![('R', 0.0, PI), ('Y', PI, 2.0 * PI)], q);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ticks() {
+        let ticks = tick_counts(160000);
+        assert_eq!((0.5, 10000), ticks[2]);
+        assert_eq!((1.0, 160000), ticks[4]);
+        assert_eq!("999", format_count(999));
+        assert_eq!("1234", format_count(1234));
+        assert_eq!("12k", format_count(12345));
+        assert_eq!("1.5M", format_count(1_500_000));
+    }
+
+    #[test]
+    fn test_annotate() {
+        let star: RgbImage = ImageBuffer::new(64, 64);
+        let img = annotate(&star, &legend(Some(Sector::full(3))));
+        let (title_height, bar_height) = margins(64);
+        assert_eq!((64, title_height + 64 + bar_height), img.dimensions());
+        // the boundary between ring 2 and 3 touches the middle of every edge.
+        assert_eq!(&RING, img.get_pixel(32, title_height));
+        // the colorbar runs from black to white.
+        let y = title_height + 64 + 8;
+        assert_eq!(&Rgb([0, 0, 0]), img.get_pixel(4, y));
+        assert_eq!(&Rgb([255, 255, 255]), img.get_pixel(59, y));
+
+        let plain = annotate(&star, &legend(None));
+        assert_eq!(&Rgb([0, 0, 0]), plain.get_pixel(32, title_height));
+    }
+
+    #[test]
+    fn test_svg_overlay() -> Result<()> {
+        let mut out = Vec::new();
+        write_svg_overlay(&mut out, &legend(Some(Sector::full(3))), 100)?;
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(2, out.matches("<circle").count());
+        assert_eq!(4, out.matches("<line ").count());
+        assert!(out.contains(">index.bin seqlen=3</text>"));
+        assert!(out.contains(">10k</text>"));
+        assert!(out.contains(r#"<tspan fill="rgb(255,0,0)">cg</tspan>"#));
+        Ok(())
+    }
+}
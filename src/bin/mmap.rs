@@ -1,11 +1,10 @@
 use anyhow::Result;
-#[path = "../database.rs"]
-mod database;
+use hustar::{Database, DatabaseMut};
 
 fn main() -> Result<()> {
-    let mut db = database::DatabaseMut::create("./test.bin", 4)?;
+    let mut db = DatabaseMut::create("./test.bin", 4)?;
     db["accg"] += 25;
-    let db = database::Database::open("./test.bin")?;
+    let db = Database::open("./test.bin")?;
     println!("{}", db["accg"]);
     Ok(())
 }
@@ -0,0 +1,246 @@
+//! Building an index from the records of a fasta file.
+
+use crate::database::DatabaseMut;
+use crate::error::{Error, Result};
+use crate::header::Header;
+use bio::io::fasta::Reader;
+use crossbeam::channel::{bounded, unbounded, Sender};
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+pub(crate) fn reader(fasta_file: &str) -> Result<Reader<std::io::BufReader<std::fs::File>>> {
+    Reader::from_file(fasta_file).map_err(|e| Error::Fasta(format!("{}: {}", fasta_file, e)))
+}
+
+fn read_into(
+    fasta_file: &str,
+    record_id: &str,
+    seqlen: usize,
+    header: &Header,
+    into: &Sender<usize>,
+) -> Result<()> {
+    info!(
+        "reading file={} with record_id={} with seqlen={}",
+        fasta_file, record_id, seqlen
+    );
+    let mut records = reader(fasta_file)?.records();
+    while let Some(Ok(record)) = records.next() {
+        if record.id() != record_id {
+            continue;
+        }
+        for (_, index) in windows(header, record.seq(), seqlen) {
+            // the receiver only hangs up after a failure of its own.
+            if into.send(index).is_err() {
+                return Ok(());
+            }
+        }
+    }
+    info!(
+        "done reading file={} with record_id={} with seqlen={}",
+        fasta_file, record_id, seqlen
+    );
+    Ok(())
+}
+
+// windows iterates the start and index of every window of length len of seq
+// that an index laid out as header describes counts.
+pub(crate) fn windows<'a>(
+    header: &'a Header,
+    seq: &'a [u8],
+    len: usize,
+) -> Box<dyn Iterator<Item = (usize, usize)> + 'a> {
+    match (&header.seed, &header.sampling) {
+        (Some(seed), _) => Box::new(seed.windows(&header.alphabet, seq, len)),
+        (None, Some(sampling)) => Box::new(sampling.sample(&header.alphabet, seq, len).into_iter()),
+        (None, None) => Box::new(header.alphabet.windows(seq, len)),
+    }
+}
+
+/// record_ids lists the ids of every record of the fasta file.
+pub fn record_ids(fasta_file: &str) -> Result<Vec<String>> {
+    let mut result = Vec::new();
+    let mut records = reader(fasta_file)?.records();
+    while let Some(Ok(record)) = records.next() {
+        result.push(record.id().to_string());
+    }
+    Ok(result)
+}
+
+/// build counts every subsequence of length 1..=seqlen of every record of the
+/// fasta file into a new index at outpath, laid out as header describes. With a
+/// spaced seed only the care positions of every window are counted, and with a
+/// sampling scheme only the sampled windows. Windows holding letters outside of
+/// the alphabet are skipped.
+pub fn build<P: Into<PathBuf>>(fasta_file: &str, outpath: P, header: Header) -> Result<()> {
+    let fasta_file = fasta_file.to_owned();
+    let cpus = num_cpus::get();
+    let seqlen = header
+        .seqlen
+        .ok_or_else(|| Error::InvalidHeader("missing seqlen".to_string()))?;
+    if let Some(seed) = &header.seed {
+        seed.check_len(seqlen)?;
+        if header.sampling.is_some() {
+            return Err(Error::InvalidSampling(
+                "sampling cannot be combined with a spaced seed".to_string(),
+            ));
+        }
+    }
+
+    info!(
+        "building index from fasta_file={} seqlen={} alphabet={} seed={:?} sampling={:?} with cpus={}",
+        fasta_file,
+        seqlen,
+        header.alphabet,
+        header.seed.as_ref().map(|s| s.to_string()),
+        header.sampling,
+        cpus
+    );
+
+    let mut db = DatabaseMut::create_with(outpath, header.clone())?;
+
+    info!("reading record ids");
+    let record_ids = record_ids(&fasta_file)?;
+    info!("done reading record ids");
+    let (tx, rx) = unbounded();
+    let (tx_sequences, rx_sequences) = bounded::<usize>(0);
+    for seqlen in 1..=seqlen {
+        for record_id in &record_ids {
+            let val = (record_id.clone(), seqlen);
+            info!("sending {:?}", val);
+            tx.send(val).expect("the receiver is held below");
+        }
+    }
+    drop(tx);
+    // one cpu is left to the inserting thread, but there is always a reader.
+    let mut workers = Vec::new();
+    for worker_id in 0..(cpus - 1).max(1) {
+        let rx = rx.clone();
+        let fasta_file = fasta_file.clone();
+        let tx_sequences = tx_sequences.clone();
+        let header = header.clone();
+        workers.push(thread::spawn(move || -> Result<()> {
+            info!("worker_id={} starting", worker_id);
+            for (record_id, seqlen) in rx {
+                read_into(&fasta_file, &record_id, seqlen, &header, &tx_sequences)?;
+            }
+            info!("worker_id={} exiting", worker_id);
+            Ok(())
+        }));
+    }
+    drop(tx_sequences);
+    let mut counter: u64 = 0;
+    let mut last = Instant::now();
+    for index in rx_sequences {
+        db[index] += 1;
+        counter += 1;
+        if last.elapsed() > Duration::from_secs_f64(1.0) {
+            info!("inserted count={} sequences", counter);
+            last = Instant::now();
+        }
+    }
+    for worker in workers {
+        worker
+            .join()
+            .map_err(|_| Error::Worker("worker panicked".to_string()))??;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::alphabet::Alphabet;
+    use crate::database::{temp_build, temp_fasta, Database};
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_build() -> Result<()> {
+        let m = temp_build(">one\nACGNAC\n>two\nac\n", Header::new(Alphabet::dna(), 2));
+        assert_eq!(3, m.get("a")?);
+        assert_eq!(3, m.get("ac")?);
+        assert_eq!(1, m.get("cg")?);
+        let index = NamedTempFile::new().unwrap();
+        assert!(matches!(
+            build("missing.fa", index.path(), Header::new(Alphabet::dna(), 2)),
+            Err(Error::Fasta(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_protein() -> Result<()> {
+        let m = temp_build(">p\nMKWVMK*MK\n", Header::new(Alphabet::protein(), 2));
+        assert_eq!("protein", m.alphabet().name());
+        assert_eq!(3, m.get("mk")?);
+        assert_eq!(1, m.get("w")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_seed() -> Result<()> {
+        let fasta = temp_fasta(">s\nACNTACGT\n");
+        let fasta = fasta.path().to_str().unwrap();
+        let index = NamedTempFile::new().unwrap();
+        let index = index.path();
+        let header = Header {
+            seed: Some("1101".parse()?),
+            ..Header::new(Alphabet::dna(), 3)
+        };
+        build(fasta, index, header.clone())?;
+        let m = Database::open(index)?;
+        assert_eq!(header.seed, m.header().seed);
+        // the windows acNt, tacg and acgt keep act, tag and act.
+        assert_eq!(2, m.get("act")?);
+        assert_eq!(2, m.get("acgt")?);
+        assert_eq!(1, m.get("taNg")?);
+        assert_eq!(2, m.get("ac")?);
+        let too_long = Header {
+            seed: Some("101".parse()?),
+            ..Header::new(Alphabet::dna(), 3)
+        };
+        assert!(matches!(
+            build(fasta, index, too_long),
+            Err(Error::InvalidSeed(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_sampling() -> Result<()> {
+        use crate::sample::{Order, Sampling};
+
+        let fasta = temp_fasta(">s\nTGCAC\n");
+        let fasta = fasta.path().to_str().unwrap();
+        let index = NamedTempFile::new().unwrap();
+        let index = index.path();
+        let sampling = Sampling {
+            scheme: "minimizer:2".parse()?,
+            order: Order::Lexicographic,
+        };
+        let header = Header {
+            sampling: Some(sampling),
+            ..Header::new(Alphabet::dna(), 2)
+        };
+        build(fasta, index, header.clone())?;
+        let m = Database::open(index)?;
+        assert_eq!(Some(sampling), m.header().sampling);
+        assert_eq!(0, m.get("tg")?);
+        assert_eq!(1, m.get("gc")?);
+        assert_eq!(1, m.get("ac")?);
+        // the first ring keeps the g, c and a of tg, gc, ca and ac.
+        assert_eq!(1, m.get("c")?);
+        assert_eq!(0, m.get("t")?);
+
+        let seeded = Header {
+            seed: Some("11".parse()?),
+            ..header
+        };
+        assert!(matches!(
+            build(fasta, index, seeded),
+            Err(Error::InvalidSampling(_))
+        ));
+        Ok(())
+    }
+}
@@ -1,112 +1,143 @@
-use anyhow::Result;
+//! The index file: a header describing the alphabet and seqlen, followed by a
+//! flat array of u32 counts, one per sequence, ordered by length and then by
+//! the symbols of the sequence.
+
+use crate::alphabet::Alphabet;
+use crate::error::{io_error, Error, Result};
+use crate::header::Header;
+use crate::kmer::Kmer;
 use core::ops::{Index, IndexMut};
 use memmap2::{Mmap, MmapMut};
 use std::path::PathBuf;
+use std::sync::Arc;
 
+/// DatabaseMut is a writable index used while building. Indexing it with a
+/// sequence outside of its alphabet or that is too long panics, get_mut
+/// returns an error instead.
 pub struct DatabaseMut {
     mmap: MmapMut,
+    header: Header,
+    offset: usize,
 }
 
+/// Database is a read only index. Clones share the same mapping.
+#[derive(Clone)]
 pub struct Database {
-    mmap: Mmap,
-    path: PathBuf,
-}
-
-fn buf_size_bytes(seqlen: usize) -> u64 {
-    let base = 4_u64;
-    let mut size = 0;
-    for l in 1..=seqlen {
-        size += base.pow(l as u32);
-    }
-    size * 8
-}
-
-fn seq_to_index(seq: &str) -> usize {
-    let unit: u64 = 4;
-    let mut base: usize = 0;
-    for l in 1..seq.len() {
-        base += unit.pow(l as u32) as usize;
-    }
-    let addr = seq_to_addr(seq);
-    let idx = base + addr;
-    idx
-}
-
-fn bits_to_seq(bits: u64, bitsize: usize) -> String {
-  let mut bits = bits;
-  let mut seq = "".to_string();
-  for _ in 0..bitsize {
-      let tail = bits & 0b11;
-      bits = bits >> 2;
-      let s = match tail {
-          0b00 => "a",
-          0b01 => "c",
-          0b10 => "g",
-          0b11 => "t",
-          _ => panic!("impossible tail"),
-      };
-      seq.push_str(s);
-  }
-  seq.chars().rev().collect::<String>()
+    mmap: Arc<Mmap>,
+    header: Arc<Header>,
+    offset: usize,
 }
 
-pub fn index_to_seq(index: usize) -> String {
-  let base = 4_usize;
-  let mut offset = 0;
-  let mut seqlen = 1;
-  while index > offset + base.pow(seqlen) - 1{
-    offset = offset + base.pow(seqlen);
-    seqlen = seqlen+1;
-  }
-  let addr = index - offset;
-  bits_to_seq(addr as u64, seqlen as usize)
+/// CountSource reads the count of every sequence of length 1..=seqlen at its
+/// position in a dense index, which is how the renderers walk rings. Database
+/// holds the counts, and a Sketch estimates them without storing every one.
+pub trait CountSource: Index<usize, Output = u32> + Clone + Send + Sync + 'static {
+    fn alphabet(&self) -> &Alphabet;
+
+    /// check_seqlen fails unless there is a count at the index of every
+    /// sequence of length 1..=max_seqlen.
+    fn check_seqlen(&self, max_seqlen: usize) -> Result<()>;
+
+    /// get returns the count of seq.
+    fn get(&self, seq: &str) -> Result<u32>;
 }
 
-fn seq_to_addr(seq: &str) -> usize {
-    let mut b: u64 = 0;
-    for l in seq.chars() {
-        let l = l.to_ascii_lowercase();
-        let p: u64 = match l {
-            'a' => 0b00,
-            'c' => 0b01,
-            'g' => 0b10,
-            't' => 0b11,
-            _ => panic!("invalid letter {}", l as char),
-        };
-        b = b << 2;
-        b = b | p;
-    }
-    b as usize
+// index_to_seq returns the dna sequence stored at index.
+pub fn index_to_seq(index: usize) -> String {
+    Kmer::from_index(index).to_string()
 }
 
 impl DatabaseMut {
+    /// create makes a zeroed dna index at path holding every sequence of
+    /// length 1..=seqlen, replacing whatever was there.
     pub fn create<P: Into<PathBuf>>(path: P, seqlen: usize) -> Result<DatabaseMut> {
+        DatabaseMut::create_with(path, Header::new(Alphabet::dna(), seqlen))
+    }
+
+    /// create_with makes a zeroed index laid out as header describes.
+    pub fn create_with<P: Into<PathBuf>>(path: P, header: Header) -> Result<DatabaseMut> {
         use std::fs::OpenOptions;
+        use std::io::Write;
 
-        let size = buf_size_bytes(seqlen);
+        let seqlen = header.seqlen.unwrap_or(0);
+        header.alphabet.check_index_len(seqlen)?;
+        let encoded = header.encode();
+        let size = header.alphabet.base_index(seqlen + 1) * std::mem::size_of::<u32>();
         let path = path.into();
-        let file = OpenOptions::new()
+        let mut file = OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
-            .open(&path)?;
-        file.set_len(size)?;
-        let mmap = unsafe { MmapMut::map_mut(&file)? };
-        Ok(DatabaseMut { mmap })
+            .truncate(true)
+            .open(&path)
+            .map_err(io_error(&path))?;
+        file.write_all(&encoded).map_err(io_error(&path))?;
+        file.set_len((encoded.len() + size) as u64)
+            .map_err(io_error(&path))?;
+        let mmap = unsafe { MmapMut::map_mut(&file).map_err(io_error(&path))? };
+        Ok(DatabaseMut {
+            mmap,
+            header,
+            offset: encoded.len(),
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn alphabet(&self) -> &Alphabet {
+        &self.header.alphabet
+    }
+
+    pub fn len(&self) -> usize {
+        (self.mmap.len() - self.offset) / std::mem::size_of::<u32>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// get_mut returns the count of seq, which is a key or a full window as
+    /// for Database::get.
+    pub fn get_mut(&mut self, seq: &str) -> Result<&mut u32> {
+        let key = self.header.key(seq);
+        let index = checked_index(self.alphabet(), &key, self.len())?;
+        Ok(&mut self[index])
     }
 }
 
+// checked_index returns the index of seq if it is valid and held by an index
+// of len sequences.
+pub(crate) fn checked_index(alphabet: &Alphabet, seq: &str, len: usize) -> Result<usize> {
+    let index = alphabet.seq_to_index(seq)?;
+    if index >= len {
+        return Err(Error::IndexTooShort {
+            len,
+            seqlen: seq.len(),
+        });
+    }
+    Ok(index)
+}
+
+// parse panics on sequences the Index impls cannot look up.
+fn parse(header: &Header, seq: &str) -> usize {
+    header
+        .alphabet
+        .seq_to_index(&header.key(seq))
+        .unwrap_or_else(|e| panic!("{}", e))
+}
+
 impl Index<&str> for DatabaseMut {
     type Output = u32;
     fn index(&self, seq: &str) -> &Self::Output {
-        let index = seq_to_index(seq);
-        &self[index]
+        &self[parse(&self.header, seq)]
     }
 }
 
 impl IndexMut<&str> for DatabaseMut {
     fn index_mut(&mut self, seq: &str) -> &mut Self::Output {
-        let index = seq_to_index(seq);
+        let index = parse(&self.header, seq);
         &mut self[index]
     }
 }
@@ -114,18 +145,43 @@ impl IndexMut<&str> for DatabaseMut {
 impl Index<usize> for DatabaseMut {
     type Output = u32;
     fn index(&self, index: usize) -> &Self::Output {
-        let (_, buf, _) = unsafe { self.mmap.align_to::<u32>() };
+        let (_, buf, _) = unsafe { self.mmap[self.offset..].align_to::<u32>() };
         &buf[index]
     }
 }
 
 impl IndexMut<usize> for DatabaseMut {
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
-        let (_, buf, _) = unsafe { self.mmap.align_to_mut::<u32>() };
+        let (_, buf, _) = unsafe { self.mmap[self.offset..].align_to_mut::<u32>() };
         &mut buf[index]
     }
 }
 
+// kmer_index returns the index of kmer, which packs nucleotides and so only
+// indexes dna databases. It panics on other alphabets, as parse panics on
+// sequences outside of the alphabet.
+fn kmer_index(header: &Header, kmer: Kmer) -> usize {
+    header
+        .alphabet
+        .check_dna("kmer lookup")
+        .unwrap_or_else(|e| panic!("{}", e));
+    kmer.index()
+}
+
+impl Index<Kmer> for DatabaseMut {
+    type Output = u32;
+    fn index(&self, kmer: Kmer) -> &Self::Output {
+        &self[kmer_index(&self.header, kmer)]
+    }
+}
+
+impl IndexMut<Kmer> for DatabaseMut {
+    fn index_mut(&mut self, kmer: Kmer) -> &mut Self::Output {
+        let index = kmer_index(&self.header, kmer);
+        &mut self[index]
+    }
+}
+
 impl Database {
     pub fn open<P: Into<PathBuf>>(path: P) -> Result<Database> {
         use std::fs::OpenOptions;
@@ -135,46 +191,212 @@ impl Database {
             .read(true)
             .write(false)
             .create(false)
-            .open(&path)?;
-        let mmap = unsafe { Mmap::map(&file)? };
-        Ok(Database { mmap, path })
+            .open(&path)
+            .map_err(io_error(&path))?;
+        let mmap = unsafe { Mmap::map(&file).map_err(io_error(&path))? };
+        let (header, offset) = Header::decode(&mmap)?;
+        if let Some(kind) = ["sketch", "presence"]
+            .iter()
+            .find(|k| header.metadata.contains_key(**k))
+        {
+            return Err(Error::InvalidHeader(format!(
+                "{:?} is a {} file, not an index",
+                path, kind
+            )));
+        }
+        Ok(Database {
+            mmap: Arc::new(mmap),
+            header: Arc::new(header),
+            offset,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn alphabet(&self) -> &Alphabet {
+        &self.header.alphabet
+    }
+
+    /// len returns the number of sequence counts the database holds.
+    pub fn len(&self) -> usize {
+        (self.mmap.len() - self.offset) / std::mem::size_of::<u32>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// get returns the count of seq, which is a key or, for spaced seed
+    /// indexes, a full window of the seed.
+    pub fn get(&self, seq: &str) -> Result<u32> {
+        Ok(self[self.index_of(seq)?])
+    }
+
+    /// index_of returns the position of the count of seq, which is looked up
+    /// as by get.
+    pub fn index_of(&self, seq: &str) -> Result<usize> {
+        let key = self.header.key(seq);
+        checked_index(self.alphabet(), &key, self.len())
+    }
+
+    /// check_seqlen fails unless the database holds every sequence of length
+    /// 1..=max_seqlen.
+    pub fn check_seqlen(&self, max_seqlen: usize) -> Result<()> {
+        self.alphabet().check_index_len(max_seqlen)?;
+        if self.len() < self.alphabet().base_index(max_seqlen + 1) {
+            return Err(Error::IndexTooShort {
+                len: self.len(),
+                seqlen: max_seqlen,
+            });
+        }
+        Ok(())
     }
 }
 
 impl Index<&str> for Database {
     type Output = u32;
     fn index(&self, seq: &str) -> &Self::Output {
-        let index = seq_to_index(seq);
-        &self[index]
+        &self[parse(&self.header, seq)]
     }
 }
 
 impl Index<usize> for Database {
     type Output = u32;
     fn index(&self, index: usize) -> &Self::Output {
-        let (_, buf, _) = unsafe { self.mmap.align_to::<u32>() };
+        let (_, buf, _) = unsafe { self.mmap[self.offset..].align_to::<u32>() };
         &buf[index]
     }
 }
 
-impl Clone for Database {
-    fn clone(&self) -> Self {
-      let next = Database::open(self.path.clone()).expect("while opening file for database clone");
-      next
+impl Index<Kmer> for Database {
+    type Output = u32;
+    fn index(&self, kmer: Kmer) -> &Self::Output {
+        &self[kmer_index(&self.header, kmer)]
+    }
+}
+
+impl CountSource for Database {
+    fn alphabet(&self) -> &Alphabet {
+        Database::alphabet(self)
+    }
+
+    fn check_seqlen(&self, max_seqlen: usize) -> Result<()> {
+        Database::check_seqlen(self, max_seqlen)
+    }
+
+    fn get(&self, seq: &str) -> Result<u32> {
+        Database::get(self, seq)
     }
 }
 
+// temp_database writes counts into a dna index of seqlen in a temporary file of
+// its own, which is removed once the index is mapped.
+#[cfg(test)]
+pub(crate) fn temp_database(seqlen: usize, counts: &[(&str, u32)]) -> Database {
+    let file = tempfile::NamedTempFile::new().expect("while creating a temporary index");
+    let mut db = DatabaseMut::create(file.path(), seqlen).expect("while creating an index");
+    for (seq, count) in counts {
+        db[*seq] = *count;
+    }
+    Database::open(file.path()).expect("while opening a temporary index")
+}
+
+// temp_fasta writes the fasta text to a temporary file of its own, which is
+// removed when it is dropped.
+#[cfg(test)]
+pub(crate) fn temp_fasta(fasta: &str) -> tempfile::NamedTempFile {
+    let file = tempfile::NamedTempFile::new().expect("while creating a temporary fasta");
+    std::fs::write(file.path(), fasta).expect("while writing a temporary fasta");
+    file
+}
+
+// temp_build builds an index of the fasta text as header describes, with the
+// fasta and the index in temporary files of their own.
+#[cfg(test)]
+pub(crate) fn temp_build(fasta: &str, header: Header) -> Database {
+    let fasta_file = temp_fasta(fasta);
+    let file = tempfile::NamedTempFile::new().expect("while creating a temporary index");
+    let fasta_path = fasta_file.path().to_str().expect("temporary paths are utf-8");
+    crate::build::build(fasta_path, file.path(), header).expect("while building an index");
+    Database::open(file.path()).expect("while opening a temporary index")
+}
+
 #[cfg(test)]
 mod test {
   use super::*;
 
   #[test]
-  fn test_index_seq_index() {
+  fn test_index_seq_index() -> Result<()> {
     for index in 0..255 {
       let seq = index_to_seq(index);
       println!("index={index} seq={seq}");
-      let indexc = seq_to_index(&seq);
+      let indexc = seq.parse::<Kmer>()?.index();
       assert_eq!(index, indexc);
     }
+    Ok(())
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn test_get() -> Result<()> {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let path = file.path();
+    let mut db = DatabaseMut::create(path, 2)?;
+    *db.get_mut("GT")? = 3;
+    assert!(matches!(db.get_mut("gn"), Err(Error::InvalidNucleotide { .. })));
+    let m = Database::open(path)?;
+    assert_eq!(3, m.get("gt")?);
+    assert_eq!(3, m.clone()["gt"]);
+    assert_eq!(3, m["gt".parse::<Kmer>()?]);
+    assert!(matches!(m.get("acgt"), Err(Error::IndexTooShort { .. })));
+    assert!(m.check_seqlen(2).is_ok());
+    assert!(m.check_seqlen(3).is_err());
+    assert!(matches!(
+      DatabaseMut::create(path, 40),
+      Err(Error::UnsupportedSeqlen { seqlen: 40, .. })
+    ));
+    let dir = tempfile::tempdir().unwrap();
+    let missing = dir.path().join("missing.bin");
+    assert!(matches!(Database::open(missing), Err(Error::Io { .. })));
+    Ok(())
+  }
+
+  #[test]
+  fn test_alphabet() -> Result<()> {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let path = file.path();
+    let alphabet = Alphabet::protein();
+    let mut db = DatabaseMut::create_with(path, Header::new(alphabet.clone(), 2))?;
+    assert_eq!(20 + 400, db.len());
+    db["wy"] = 5;
+    assert!(matches!(db.get_mut("wb"), Err(Error::InvalidSymbol { .. })));
+    let m = Database::open(path)?;
+    assert_eq!(&alphabet, m.alphabet());
+    assert_eq!(Some(2), m.header().seqlen);
+    assert_eq!(5, m.get("WY")?);
+    assert_eq!(5, m[20 + 18 * 20 + 19]);
+    assert!(m.check_seqlen(2).is_ok());
+
+    // files without a header open as dna.
+    let legacy = tempfile::NamedTempFile::new().unwrap();
+    let mut counts = vec![0_u8; 4 * 20];
+    counts[4 * 2] = 7;
+    std::fs::write(legacy.path(), &counts).unwrap();
+    let m = Database::open(legacy.path())?;
+    assert!(m.alphabet().is_dna());
+    assert_eq!(None, m.header().seqlen);
+    assert_eq!(7, m["g"]);
+    Ok(())
+  }
+
+  #[test]
+  #[should_panic(expected = "kmer lookup")]
+  fn test_kmer_protein() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let header = Header::new(Alphabet::protein(), 1);
+    DatabaseMut::create_with(file.path(), header).unwrap();
+    let m = Database::open(file.path()).unwrap();
+    let _ = m["a".parse::<Kmer>().unwrap()];
+  }
+}
@@ -0,0 +1,279 @@
+// diff compares two indexes built with the same seqlen. Every pixel is
+// normalized by the total count of its ring in each index so that indexes built
+// from genomes of different sizes are comparable.
+
+use crate::accumulator::Accumulator;
+use crate::database::{index_to_seq, Database};
+use crate::error::{Error, Result};
+use crate::render::Counts;
+use crate::traverse::Point;
+use clap::ArgEnum;
+use image::{ImageBuffer, Rgb, RgbImage};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::io::Write;
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq)]
+pub enum Metric {
+    // log2 of the ratio of frequencies with a pseudocount of one.
+    LogRatio,
+    // difference of frequencies.
+    Difference,
+}
+
+impl Metric {
+    pub fn compare(&self, a: u64, total_a: u64, b: u64, total_b: u64) -> f64 {
+        match self {
+            Metric::LogRatio => {
+                let fa = (a + 1) as f64 / (total_a + 1) as f64;
+                let fb = (b + 1) as f64 / (total_b + 1) as f64;
+                (fa / fb).log2()
+            }
+            Metric::Difference => {
+                let fa = if total_a == 0 {
+                    0.0
+                } else {
+                    a as f64 / total_a as f64
+                };
+                let fb = if total_b == 0 {
+                    0.0
+                } else {
+                    b as f64 / total_b as f64
+                };
+                fa - fb
+            }
+        }
+    }
+}
+
+// ring_totals returns the total count of every ring from 1..=max_seqlen,
+// indexed by seqlen.
+pub fn ring_totals(m: &Database, max_seqlen: usize) -> Result<Vec<u64>> {
+    m.alphabet().check_dna("diff")?;
+    m.check_seqlen(max_seqlen)?;
+    let mut acc = Accumulator::default();
+    let mut totals = vec![0; max_seqlen + 1];
+    let alphabet = m.alphabet();
+    for (seqlen, total) in totals.iter_mut().enumerate().skip(1) {
+        *total = acc.sum_to(
+            m,
+            alphabet.base_index(seqlen),
+            alphabet.base_index(seqlen + 1),
+        );
+    }
+    Ok(totals)
+}
+
+// compare_points applies the metric to every pixel. Values are written in the
+// same row major order as the counts.
+pub fn compare_points(
+    metric: Metric,
+    points: &[Point],
+    a: &Counts,
+    totals_a: &[u64],
+    b: &Counts,
+    totals_b: &[u64],
+) -> Vec<f64> {
+    let mut values = vec![0.0; a.width * a.height];
+    for p in points {
+        let (x, y) = (p.w as usize, p.h as usize);
+        values[y * a.width + x] = metric.compare(
+            a.get(x, y),
+            totals_a[p.seqlen],
+            b.get(x, y),
+            totals_b[p.seqlen],
+        );
+    }
+    values
+}
+
+// diverging colors values from blue through white to red, where full
+// saturation is reached at the largest absolute value.
+pub fn diverging(values: &[f64], width: usize, height: usize) -> RgbImage {
+    let max = values.iter().fold(0.0_f64, |acc, v| acc.max(v.abs()));
+    ImageBuffer::from_fn(width as u32, height as u32, |x, y| {
+        let v = values[y as usize * width + x as usize];
+        let p = if max == 0.0 { 0.0 } else { v / max };
+        let fade = (255.0 * (1.0 - p.abs())) as u8;
+        if p >= 0.0 {
+            Rgb([255, fade, fade])
+        } else {
+            Rgb([fade, fade, 255])
+        }
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RingStats {
+    pub seqlen: usize,
+    pub total_a: u64,
+    pub total_b: u64,
+    pub pearson: f64,
+}
+
+// ring_stats correlates the counts of every sequence of each ring between the
+// two indexes.
+pub fn ring_stats(
+    a: &Database,
+    totals_a: &[u64],
+    b: &Database,
+    totals_b: &[u64],
+) -> Vec<RingStats> {
+    let mut stats = Vec::new();
+    let alphabet = a.alphabet();
+    for seqlen in 1..totals_a.len() {
+        let (gte, lt) = (alphabet.base_index(seqlen), alphabet.base_index(seqlen + 1));
+        let n = (lt - gte) as f64;
+        let (mut sa, mut sb, mut saa, mut sbb, mut sab) = (0.0, 0.0, 0.0, 0.0, 0.0);
+        for index in gte..lt {
+            let (ca, cb) = (a[index] as f64, b[index] as f64);
+            sa += ca;
+            sb += cb;
+            saa += ca * ca;
+            sbb += cb * cb;
+            sab += ca * cb;
+        }
+        let cov = sab - sa * sb / n;
+        let var = (saa - sa * sa / n) * (sbb - sb * sb / n);
+        let pearson = if var > 0.0 {
+            cov / var.sqrt()
+        } else {
+            f64::NAN
+        };
+        stats.push(RingStats {
+            seqlen,
+            total_a: totals_a[seqlen],
+            total_b: totals_b[seqlen],
+            pearson,
+        });
+    }
+    stats
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Difference {
+    pub seq: String,
+    pub count_a: u32,
+    pub count_b: u32,
+    pub value: f64,
+}
+
+// top_differences returns the n sequences of length seqlen that differ the most
+// under the metric, largest absolute value first.
+pub fn top_differences(
+    metric: Metric,
+    a: &Database,
+    totals_a: &[u64],
+    b: &Database,
+    totals_b: &[u64],
+    seqlen: usize,
+    n: usize,
+) -> Vec<Difference> {
+    // f64 is not Ord, so the heap orders on the bits of the absolute value,
+    // which sort the same way for non negative floats. Ties keep the lowest
+    // index.
+    let mut heap: BinaryHeap<Reverse<(u64, Reverse<usize>)>> = BinaryHeap::with_capacity(n + 1);
+    let value = |index: usize| {
+        metric.compare(
+            a[index] as u64,
+            totals_a[seqlen],
+            b[index] as u64,
+            totals_b[seqlen],
+        )
+    };
+    let alphabet = a.alphabet();
+    for index in alphabet.base_index(seqlen)..alphabet.base_index(seqlen + 1) {
+        heap.push(Reverse((value(index).abs().to_bits(), Reverse(index))));
+        if heap.len() > n {
+            heap.pop();
+        }
+    }
+    let mut top: Vec<(u64, Reverse<usize>)> = heap.into_iter().map(|Reverse(v)| v).collect();
+    top.sort_by(|l, r| r.cmp(l));
+    top.into_iter()
+        .map(|(_, Reverse(index))| Difference {
+            seq: index_to_seq(index),
+            count_a: a[index],
+            count_b: b[index],
+            value: value(index),
+        })
+        .collect()
+}
+
+pub fn write_ring_stats<W: Write>(out: &mut W, stats: &[RingStats]) -> Result<()> {
+    writeln!(out, "seqlen\ttotal_a\ttotal_b\tpearson").map_err(Error::Output)?;
+    for s in stats {
+        writeln!(
+            out,
+            "{}\t{}\t{}\t{:.6}",
+            s.seqlen, s.total_a, s.total_b, s.pearson
+        )
+        .map_err(Error::Output)?;
+    }
+    Ok(())
+}
+
+pub fn write_differences<W: Write>(out: &mut W, top: &[Difference]) -> Result<()> {
+    writeln!(out, "seq\tcount_a\tcount_b\tvalue").map_err(Error::Output)?;
+    for d in top {
+        writeln!(
+            out,
+            "{}\t{}\t{}\t{:.6}",
+            d.seq, d.count_a, d.count_b, d.value
+        )
+        .map_err(Error::Output)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::temp_database;
+
+    #[test]
+    fn test_compare() {
+        assert_eq!(1.0, Metric::LogRatio.compare(3, 3, 1, 3));
+        assert_eq!(0.0, Metric::LogRatio.compare(0, 10, 0, 10));
+        assert_eq!(0.25, Metric::Difference.compare(1, 2, 1, 4));
+        assert_eq!(0.0, Metric::Difference.compare(0, 0, 0, 0));
+    }
+
+    #[test]
+    fn test_diff() -> Result<()> {
+        let a = temp_database(2, &[("a", 2), ("c", 2), ("ac", 2), ("ca", 2)]);
+        let b = temp_database(2, &[("a", 4), ("c", 4), ("ac", 1), ("ca", 3)]);
+        let totals_a = ring_totals(&a, 2)?;
+        let totals_b = ring_totals(&b, 2)?;
+        assert_eq!(vec![0, 4, 4], totals_a);
+        assert_eq!(vec![0, 8, 4], totals_b);
+        assert!(ring_totals(&a, 3).is_err());
+
+        let stats = ring_stats(&a, &totals_a, &b, &totals_b);
+        assert_eq!(2, stats.len());
+        assert!((stats[0].pearson - 1.0).abs() < 1e-9);
+        assert!(stats[1].pearson < 1.0);
+
+        let top = top_differences(Metric::Difference, &a, &totals_a, &b, &totals_b, 2, 2);
+        assert_eq!(2, top.len());
+        assert_eq!(("ac", 0.25), (&top[0].seq[..], top[0].value));
+        assert_eq!(("ca", -0.25), (&top[1].seq[..], top[1].value));
+
+        let mut out = Vec::new();
+        write_differences(&mut out, &top)?;
+        assert_eq!(
+            "seq\tcount_a\tcount_b\tvalue\nac\t2\t1\t0.250000\nca\t2\t3\t-0.250000\n",
+            String::from_utf8(out).unwrap()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_diverging() {
+        let img = diverging(&[1.0, -0.5, 0.0, -2.0], 2, 2);
+        assert_eq!(&Rgb([255, 127, 127]), img.get_pixel(0, 0));
+        assert_eq!(&Rgb([191, 191, 255]), img.get_pixel(1, 0));
+        assert_eq!(&Rgb([255, 255, 255]), img.get_pixel(0, 1));
+        assert_eq!(&Rgb([0, 0, 255]), img.get_pixel(1, 1));
+    }
+}
@@ -0,0 +1,322 @@
+// distance compares the genomic signatures of indexes, the counts of one ring
+// of each, and joins them into a neighbor joining tree. Every pair of indexes
+// streams its two rings side by side, and pairs are split across a thread per
+// cpu.
+
+use crate::database::Database;
+use crate::error::{Error, Result};
+use clap::ArgEnum;
+use std::io::Write;
+use std::thread;
+use tracing::info;
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq)]
+pub enum Metric {
+    // Euclidean distance of the frequencies.
+    Euclidean,
+    // one minus the cosine similarity of the counts.
+    Cosine,
+    // d2 is (1 - D2 / (|a| |b|)) / 2, where D2 is the dot product of the
+    // counts.
+    D2,
+    // d2star is d2 over counts centered on, and scaled by, the counts a
+    // Markov chain of order 0 expects from the letters of each index.
+    D2star,
+    // square root of the Jensen-Shannon divergence of the frequencies, in
+    // bits, which lies in [0, 1].
+    JensenShannon,
+}
+
+// Profile is the ring of one index with the totals the metrics normalize by.
+pub struct Profile<'a> {
+    m: &'a Database,
+    seqlen: usize,
+    total: f64,
+    // letters holds the frequency of every symbol, from the first ring.
+    letters: Vec<f64>,
+}
+
+impl<'a> Profile<'a> {
+    pub fn new(m: &'a Database, seqlen: usize) -> Result<Profile<'a>> {
+        m.check_seqlen(seqlen)?;
+        let alphabet = m.alphabet();
+        let ring = |len| alphabet.base_index(len)..alphabet.base_index(len + 1);
+        let total: f64 = ring(seqlen).map(|i| m[i] as f64).sum();
+        if total == 0.0 {
+            return Err(Error::EmptyRing { seqlen });
+        }
+        let letters: f64 = ring(1).map(|i| m[i] as f64).sum();
+        let letters = ring(1).map(|i| m[i] as f64 / letters).collect();
+        Ok(Profile {
+            m,
+            seqlen,
+            total,
+            letters,
+        })
+    }
+
+    fn range(&self) -> std::ops::Range<usize> {
+        let alphabet = self.m.alphabet();
+        alphabet.base_index(self.seqlen)..alphabet.base_index(self.seqlen + 1)
+    }
+
+    // expected returns the count of the sequence at index that the letter
+    // frequencies expect.
+    fn expected(&self, index: usize) -> f64 {
+        let radix = self.letters.len();
+        let mut addr = index - self.range().start;
+        let mut p = self.total;
+        for _ in 0..self.seqlen {
+            p *= self.letters[addr % radix];
+            addr /= radix;
+        }
+        p
+    }
+}
+
+// check_comparable fails unless the indexes count the same kind of windows.
+pub fn check_comparable(ms: &[Database]) -> Result<()> {
+    for m in ms.iter().skip(1) {
+        let (a, b) = (ms[0].header(), m.header());
+        if a.alphabet != b.alphabet || a.seed != b.seed || a.sampling != b.sampling {
+            return Err(Error::Incomparable(
+                "alphabet, seed or sampling".to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+pub fn distance(metric: Metric, a: &Profile, b: &Profile) -> f64 {
+    let pairs = a.range().map(|i| (i, a.m[i] as f64, b.m[i] as f64));
+    match metric {
+        Metric::Euclidean => pairs
+            .map(|(_, ca, cb)| (ca / a.total - cb / b.total).powi(2))
+            .sum::<f64>()
+            .sqrt(),
+        Metric::Cosine | Metric::D2 => {
+            let (mut ab, mut aa, mut bb) = (0.0, 0.0, 0.0);
+            for (_, ca, cb) in pairs {
+                ab += ca * cb;
+                aa += ca * ca;
+                bb += cb * cb;
+            }
+            let cosine = ab / (aa.sqrt() * bb.sqrt());
+            if metric == Metric::Cosine {
+                1.0 - cosine
+            } else {
+                (1.0 - cosine) / 2.0
+            }
+        }
+        Metric::D2star => {
+            let (mut ab, mut aa, mut bb) = (0.0, 0.0, 0.0);
+            for (i, ca, cb) in pairs {
+                let (ea, eb) = (a.expected(i), b.expected(i));
+                if ea == 0.0 || eb == 0.0 {
+                    continue;
+                }
+                let (ta, tb) = (ca - ea, cb - eb);
+                ab += ta * tb / (ea * eb).sqrt();
+                aa += ta * ta / ea;
+                bb += tb * tb / eb;
+            }
+            (1.0 - ab / (aa.sqrt() * bb.sqrt())) / 2.0
+        }
+        Metric::JensenShannon => {
+            let mut divergence = 0.0;
+            for (_, ca, cb) in pairs {
+                let (p, q) = (ca / a.total, cb / b.total);
+                let mean = (p + q) / 2.0;
+                if p > 0.0 {
+                    divergence += p * (p / mean).log2() / 2.0;
+                }
+                if q > 0.0 {
+                    divergence += q * (q / mean).log2() / 2.0;
+                }
+            }
+            divergence.max(0.0).sqrt()
+        }
+    }
+}
+
+// matrix returns the distance of every pair of indexes over their rings of
+// seqlen.
+pub fn matrix(metric: Metric, ms: &[Database], seqlen: usize) -> Result<Vec<Vec<f64>>> {
+    check_comparable(ms)?;
+    let profiles = ms
+        .iter()
+        .map(|m| Profile::new(m, seqlen))
+        .collect::<Result<Vec<Profile>>>()?;
+    let mut pairs = Vec::new();
+    for i in 0..ms.len() {
+        for j in i + 1..ms.len() {
+            pairs.push((i, j));
+        }
+    }
+    info!(
+        "comparing {} pairs of seqlen={} with {:?}",
+        pairs.len(),
+        seqlen,
+        metric
+    );
+    let chunk_len = pairs.len().div_ceil(num_cpus::get()).max(1);
+    let distances: Vec<f64> = thread::scope(|s| {
+        let profiles = &profiles;
+        let workers: Vec<_> = pairs
+            .chunks(chunk_len)
+            .map(|chunk| {
+                s.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|(i, j)| distance(metric, &profiles[*i], &profiles[*j]))
+                        .collect::<Vec<f64>>()
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|w| w.join().expect("while joining distance worker"))
+            .collect()
+    });
+    let mut matrix = vec![vec![0.0; ms.len()]; ms.len()];
+    for ((i, j), d) in pairs.into_iter().zip(distances) {
+        matrix[i][j] = d;
+        matrix[j][i] = d;
+    }
+    Ok(matrix)
+}
+
+pub fn write_matrix<W: Write>(out: &mut W, names: &[String], matrix: &[Vec<f64>]) -> Result<()> {
+    writeln!(out, "\t{}", names.join("\t")).map_err(Error::Output)?;
+    for (name, row) in names.iter().zip(matrix) {
+        let row: Vec<String> = row.iter().map(|d| format!("{:.6}", d)).collect();
+        writeln!(out, "{}\t{}", name, row.join("\t")).map_err(Error::Output)?;
+    }
+    Ok(())
+}
+
+// neighbor_joining returns the neighbor joining tree of the distance matrix in
+// newick format. Negative branch lengths are clamped to zero.
+pub fn neighbor_joining(names: &[String], matrix: &[Vec<f64>]) -> String {
+    let mut nodes: Vec<String> = names.to_vec();
+    let mut d: Vec<Vec<f64>> = matrix.to_vec();
+    let branch = |node: &str, len: f64| format!("{}:{:.6}", node, len.max(0.0));
+    while nodes.len() > 3 {
+        let n = nodes.len();
+        let sums: Vec<f64> = d.iter().map(|row| row.iter().sum()).collect();
+        let mut best = (f64::INFINITY, 0, 1);
+        for i in 0..n {
+            for j in i + 1..n {
+                let q = (n - 2) as f64 * d[i][j] - sums[i] - sums[j];
+                if q < best.0 {
+                    best = (q, i, j);
+                }
+            }
+        }
+        let (_, i, j) = best;
+        let li = d[i][j] / 2.0 + (sums[i] - sums[j]) / (2.0 * (n - 2) as f64);
+        let lj = d[i][j] - li;
+        let joined = format!("({},{})", branch(&nodes[i], li), branch(&nodes[j], lj));
+        let to_joined: Vec<f64> = (0..n)
+            .map(|k| (d[i][k] + d[j][k] - d[i][j]) / 2.0)
+            .collect();
+        // j is removed first since it is the larger of the two.
+        for k in [j, i] {
+            nodes.remove(k);
+            d.remove(k);
+            for row in d.iter_mut() {
+                row.remove(k);
+            }
+        }
+        let to_joined: Vec<f64> = (0..n)
+            .filter(|k| *k != i && *k != j)
+            .map(|k| to_joined[k])
+            .collect();
+        for (row, to) in d.iter_mut().zip(&to_joined) {
+            row.push(*to);
+        }
+        d.push(to_joined.into_iter().chain([0.0]).collect());
+        nodes.push(joined);
+    }
+    match nodes.len() {
+        0 => ";".to_string(),
+        1 => format!("{};", nodes[0]),
+        2 => format!(
+            "({},{});",
+            branch(&nodes[0], d[0][1] / 2.0),
+            branch(&nodes[1], d[0][1] / 2.0)
+        ),
+        _ => format!(
+            "({},{},{});",
+            branch(&nodes[0], (d[0][1] + d[0][2] - d[1][2]) / 2.0),
+            branch(&nodes[1], (d[0][1] + d[1][2] - d[0][2]) / 2.0),
+            branch(&nodes[2], (d[0][2] + d[1][2] - d[0][1]) / 2.0)
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::temp_database;
+
+    #[test]
+    fn test_distance() -> Result<()> {
+        let a = temp_database(2, &[("a", 2), ("c", 2), ("ac", 2), ("ca", 2)]);
+        let b = temp_database(2, &[("a", 4), ("c", 4), ("ac", 1), ("ca", 3)]);
+        let (pa, pb) = (Profile::new(&a, 2)?, Profile::new(&b, 2)?);
+        let close = |l: f64, r: f64| (l - r).abs() < 1e-9;
+        assert!(close(
+            0.125_f64.sqrt(),
+            distance(Metric::Euclidean, &pa, &pb)
+        ));
+        let cosine = 1.0 - 8.0 / (8.0_f64.sqrt() * 10.0_f64.sqrt());
+        assert!(close(cosine, distance(Metric::Cosine, &pa, &pb)));
+        assert!(close(cosine / 2.0, distance(Metric::D2, &pa, &pb)));
+        for metric in [
+            Metric::Euclidean,
+            Metric::Cosine,
+            Metric::D2,
+            Metric::D2star,
+            Metric::JensenShannon,
+        ] {
+            assert!(close(0.0, distance(metric, &pa, &pa)), "{:?}", metric);
+            let d = distance(metric, &pa, &pb);
+            assert!(close(d, distance(metric, &pb, &pa)), "{:?}", metric);
+        }
+        let js = distance(Metric::JensenShannon, &pa, &pb);
+        assert!(js > 0.0 && js < 1.0);
+
+        let ms = [a, b];
+        let matrix = matrix(Metric::Euclidean, &ms, 2)?;
+        assert_eq!(0.0, matrix[0][0]);
+        assert_eq!(matrix[0][1], matrix[1][0]);
+        assert!(super::matrix(Metric::Euclidean, &ms, 3).is_err());
+        let empty = temp_database(2, &[]);
+        assert!(matches!(
+            Profile::new(&empty, 2),
+            Err(Error::EmptyRing { seqlen: 2 })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_neighbor_joining() {
+        let names: Vec<String> = ["a", "b", "c", "d"].iter().map(|n| n.to_string()).collect();
+        // the additive tree ((a:1,b:2):3,c:4,d:5) with c and d joined last.
+        let matrix = vec![
+            vec![0.0, 3.0, 8.0, 9.0],
+            vec![3.0, 0.0, 9.0, 10.0],
+            vec![8.0, 9.0, 0.0, 9.0],
+            vec![9.0, 10.0, 9.0, 0.0],
+        ];
+        assert_eq!(
+            "(c:4.000000,d:5.000000,(a:1.000000,b:2.000000):3.000000);",
+            neighbor_joining(&names, &matrix)
+        );
+        assert_eq!(
+            "(a:1.500000,b:1.500000);",
+            neighbor_joining(&names[..2], &[vec![0.0, 3.0], vec![3.0, 0.0]])
+        );
+    }
+}
@@ -0,0 +1,180 @@
+// enrichment compares the count of every sequence with the count a Markov chain
+// of order m expects from the shorter rings of the same index, which removes
+// the part of a count that base composition alone explains. The expected count
+// of w = w_1..w_k is the maximal order estimate
+//
+//   E(w) = N(w_1..w_m+1) * prod P(w_j+m | w_j..w_j+m-1), j = 2..k-m
+//
+// where N is the count in the index and P the transitions of the chain that
+// Markov::from_database derives from it. Sequences no longer than m + 1 are
+// expected as often as they occur.
+
+use crate::database::Database;
+use crate::error::{Error, Result};
+use crate::markov::Markov;
+use crate::traverse::Point;
+use std::io::Write;
+
+pub struct Model<'a> {
+    m: &'a Database,
+    chain: Markov,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Enrichment {
+    pub seq: String,
+    pub observed: u32,
+    pub expected: f64,
+    // ratio is observed / expected, and z the Poisson z-score
+    // (observed - expected) / sqrt(expected). Both are NaN where nothing is
+    // expected.
+    pub ratio: f64,
+    pub z: f64,
+}
+
+impl<'a> Model<'a> {
+    // new builds the model of order over the rings of m up to seqlen.
+    pub fn new(m: &'a Database, order: usize, seqlen: usize) -> Result<Model<'a>> {
+        if order + 2 > seqlen {
+            return Err(Error::InvalidModel(format!(
+                "order={} needs sequences of at least {} to expect anything, not {}",
+                order,
+                order + 2,
+                seqlen
+            )));
+        }
+        m.check_seqlen(seqlen)?;
+        let chain = Markov::from_database(m, order, 0.0)?;
+        Ok(Model { m, chain })
+    }
+
+    // expected returns E of the sequence at index, which is of length seqlen.
+    pub fn expected(&self, index: usize, seqlen: usize) -> f64 {
+        let m = self.chain.order();
+        if seqlen <= m + 1 {
+            return self.m[index] as f64;
+        }
+        let alphabet = self.m.alphabet();
+        let mut addr = index - alphabet.base_index(seqlen);
+        let mut codes = vec![0; seqlen];
+        for c in codes.iter_mut().rev() {
+            *c = addr % alphabet.radix();
+            addr /= alphabet.radix();
+        }
+        let first = codes[..m + 1]
+            .iter()
+            .fold(0, |acc, c| acc * alphabet.radix() + c);
+        let mut expected = self.m[alphabet.base_index(m + 1) + first] as f64;
+        for j in 1..seqlen - m {
+            expected *= self.chain.probability(&codes[j..j + m + 1]);
+        }
+        expected
+    }
+
+    pub fn enrichment(&self, index: usize, seqlen: usize) -> Enrichment {
+        let observed = self.m[index];
+        let expected = self.expected(index, seqlen);
+        let (ratio, z) = if expected > 0.0 {
+            (
+                observed as f64 / expected,
+                (observed as f64 - expected) / expected.sqrt(),
+            )
+        } else {
+            (f64::NAN, f64::NAN)
+        };
+        Enrichment {
+            seq: self.m.alphabet().index_to_seq(index),
+            observed,
+            expected,
+            ratio,
+            z,
+        }
+    }
+
+    // write_ring writes the enrichment of every sequence of length seqlen as
+    // tsv, in index order.
+    pub fn write_ring<W: Write>(&self, out: &mut W, seqlen: usize) -> Result<()> {
+        let alphabet = self.m.alphabet();
+        writeln!(out, "seq\tobserved\texpected\tratio\tz").map_err(Error::Output)?;
+        for index in alphabet.base_index(seqlen)..alphabet.base_index(seqlen + 1) {
+            let e = self.enrichment(index, seqlen);
+            writeln!(
+                out,
+                "{}\t{}\t{:.6}\t{:.6}\t{:.6}",
+                e.seq, e.observed, e.expected, e.ratio, e.z
+            )
+            .map_err(Error::Output)?;
+        }
+        Ok(())
+    }
+
+    // log_ratios returns log2 of the observed over the expected count of the
+    // index range of every point, with a pseudocount of one, in row major order
+    // of a width wide image. Points are expected sorted as make_points sorts
+    // them, so that points sharing a range are summed once.
+    pub fn log_ratios(&self, points: &[Point], width: usize, height: usize) -> Vec<f64> {
+        let mut values = vec![0.0; width * height];
+        let mut last: Option<((usize, usize), f64)> = None;
+        for p in points {
+            let range = p.index_range();
+            let value = match last {
+                Some((r, value)) if r == range => value,
+                _ => {
+                    let (observed, expected) = (range.0..range.1).fold((0.0, 0.0), |(o, e), i| {
+                        (o + self.m[i] as f64, e + self.expected(i, p.seqlen))
+                    });
+                    ((observed + 1.0) / (expected + 1.0)).log2()
+                }
+            };
+            values[p.h as usize * width + p.w as usize] = value;
+            last = Some((range, value));
+        }
+        values
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::alphabet::Alphabet;
+    use crate::database::temp_build;
+    use crate::header::Header;
+    use crate::traverse::{make_points, Sector};
+
+    #[test]
+    fn test_enrichment() -> Result<()> {
+        let m = temp_build(">one\nACGTAC\n", Header::new(Alphabet::dna(), 3));
+        let dna = Alphabet::dna();
+        // a and c are 2 of 6 letters, so ac is expected 2 * 2 / 6 times.
+        let model = Model::new(&m, 0, 3)?;
+        let ac = model.enrichment(dna.seq_to_index("ac")?, 2);
+        assert_eq!(2, ac.observed);
+        assert!((ac.expected - 2.0 / 3.0).abs() < 1e-12);
+        assert!((ac.ratio - 3.0).abs() < 1e-12);
+        assert_eq!(2.0, model.expected(dna.seq_to_index("a")?, 1));
+        assert!(Model::new(&m, 2, 3).is_err());
+        assert!(Model::new(&m, 0, 4).is_err());
+
+        // acg is expected N(ac) P(g | c) times by the order 1 chain, and c is
+        // only ever followed by g.
+        let model = Model::new(&m, 1, 3)?;
+        assert_eq!(2.0, model.expected(dna.seq_to_index("acg")?, 3));
+        assert_eq!(0.0, model.expected(dna.seq_to_index("aaa")?, 3));
+        assert!(model.enrichment(dna.seq_to_index("aaa")?, 3).z.is_nan());
+
+        let mut out = Vec::new();
+        model.write_ring(&mut out, 2)?;
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(1 + 16, out.lines().count());
+        assert_eq!(
+            "ac\t2\t2.000000\t1.000000\t0.000000",
+            out.lines().nth(2).unwrap()
+        );
+
+        let points = make_points(20, 20, Sector::full(3));
+        let values = model.log_ratios(&points, 20, 20);
+        assert_eq!(400, values.len());
+        assert!(values.iter().all(|v| v.is_finite()));
+        Ok(())
+    }
+}
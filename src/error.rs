@@ -0,0 +1,89 @@
+//! Errors returned by the index and its readers.
+
+use std::path::PathBuf;
+
+/// Error is every way building, opening or querying an index can fail.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("while accessing {path:?}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("invalid nucleotide {letter:?} in {seq:?}")]
+    InvalidNucleotide { letter: char, seq: String },
+    #[error("invalid {alphabet} letter {letter:?} in {seq:?}")]
+    InvalidSymbol {
+        letter: char,
+        seq: String,
+        alphabet: String,
+    },
+    #[error("invalid alphabet: {0}")]
+    InvalidAlphabet(String),
+    #[error("the {backend} only supports dna, not {alphabet}")]
+    UnsupportedAlphabet {
+        alphabet: String,
+        backend: &'static str,
+    },
+    #[error("invalid seed: {0}")]
+    InvalidSeed(String),
+    #[error("invalid sampling: {0}")]
+    InvalidSampling(String),
+    #[error("invalid positions: {0}")]
+    InvalidPositions(String),
+    #[error("invalid presence index: {0}")]
+    InvalidPresence(String),
+    #[error("invalid sketch: {0}")]
+    InvalidSketch(String),
+    #[error("invalid markov model: {0}")]
+    InvalidModel(String),
+    #[error("invalid index header: {0}")]
+    InvalidHeader(String),
+    #[error("sequence {seq:?} is {len} long which is more than the supported {max}")]
+    TooLong { seq: String, len: usize, max: usize },
+    #[error("seqlen={seqlen} is longer than the {backend} supports, at most {max}")]
+    UnsupportedSeqlen {
+        seqlen: usize,
+        max: usize,
+        backend: &'static str,
+    },
+    #[error("index holds {len} sequences which is too few for seqlen={seqlen}")]
+    IndexTooShort { len: usize, seqlen: usize },
+    #[error("index holds no sequences of seqlen={seqlen}")]
+    EmptyRing { seqlen: usize },
+    #[error("indexes differ in {0}")]
+    Incomparable(String),
+    #[error("invalid sector: {0}")]
+    InvalidSector(String),
+    #[error("while reading fasta: {0}")]
+    Fasta(String),
+    #[error("a build worker exited early: {0}")]
+    Worker(String),
+    #[error("while writing output: {0}")]
+    Output(std::io::Error),
+    #[error("while writing image {path:?}: {source}")]
+    Image {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+    #[error("invalid output: {0}")]
+    InvalidOutput(String),
+    #[error("invalid motif: {0}")]
+    InvalidMotif(String),
+    #[error("invalid tile pyramid: {0}")]
+    InvalidPyramid(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+// io_error attaches the path to an io error.
+pub(crate) fn io_error<P: Into<PathBuf>>(path: P) -> impl FnOnce(std::io::Error) -> Error {
+    let path = path.into();
+    move |source| Error::Io { path, source }
+}
+
+// image_error attaches the path to an image error.
+pub(crate) fn image_error<P: Into<PathBuf>>(path: P) -> impl FnOnce(image::ImageError) -> Error {
+    let path = path.into();
+    move |source| Error::Image { path, source }
+}
@@ -0,0 +1,155 @@
+// fcgr exports the ring of one seqlen as a frequency chaos game representation
+// matrix. The 2-bit address of every sequence is split into interleaved column
+// and row bits the same way the fcgr layout places it on an image.
+
+use crate::database::Database;
+use crate::error::{image_error, io_error, Error, Result};
+use crate::layout::deinterleave;
+use byteorder::{LittleEndian, WriteBytesExt};
+use image::{GrayImage, ImageBuffer};
+use std::io::Write;
+use std::path::Path;
+
+pub struct Matrix {
+    pub side: usize,
+    // counts holds side x side counts in row major order.
+    pub counts: Vec<u32>,
+}
+
+impl Matrix {
+    pub fn get(&self, col: usize, row: usize) -> u32 {
+        self.counts[row * self.side + col]
+    }
+}
+
+pub fn matrix(m: &Database, seqlen: usize) -> Result<Matrix> {
+    m.alphabet().check_dna("fcgr")?;
+    if seqlen == 0 {
+        return Err(Error::EmptyRing { seqlen });
+    }
+    m.check_seqlen(seqlen)?;
+    let side = 1 << seqlen;
+    let base = m.alphabet().base_index(seqlen);
+    let mut counts = vec![0; side * side];
+    for addr in 0..side * side {
+        let (col, row) = deinterleave(addr as u64, seqlen as u32);
+        counts[row as usize * side + col as usize] = m[base + addr];
+    }
+    Ok(Matrix { side, counts })
+}
+
+pub fn write_csv<W: Write>(out: &mut W, matrix: &Matrix) -> Result<()> {
+    for row in matrix.counts.chunks(matrix.side) {
+        let line: Vec<String> = row.iter().map(|c| c.to_string()).collect();
+        writeln!(out, "{}", line.join(",")).map_err(Error::Output)?;
+    }
+    Ok(())
+}
+
+// write_npy writes the matrix as a version 1.0 numpy array of little endian
+// uint32.
+pub fn write_npy<W: Write>(out: &mut W, matrix: &Matrix) -> Result<()> {
+    let mut header = format!(
+        "{{'descr': '<u4', 'fortran_order': False, 'shape': ({}, {}), }}",
+        matrix.side, matrix.side
+    );
+    // magic, version and header length take 10 bytes, and the header is padded
+    // with spaces and ends in a newline so that the data is 64 byte aligned.
+    while (10 + header.len() + 1) % 64 != 0 {
+        header.push(' ');
+    }
+    header.push('\n');
+    out.write_all(b"\x93NUMPY\x01\x00").map_err(Error::Output)?;
+    out.write_u16::<LittleEndian>(header.len() as u16)
+        .map_err(Error::Output)?;
+    out.write_all(header.as_bytes()).map_err(Error::Output)?;
+    for c in &matrix.counts {
+        out.write_u32::<LittleEndian>(*c).map_err(Error::Output)?;
+    }
+    Ok(())
+}
+
+pub fn to_image(matrix: &Matrix) -> GrayImage {
+    let max = matrix.counts.iter().copied().max().unwrap_or(0);
+    ImageBuffer::from_fn(matrix.side as u32, matrix.side as u32, |x, y| {
+        let c = matrix.get(x as usize, y as usize);
+        let p = if max == 0 { 0.0 } else { c as f64 / max as f64 };
+        let l = (p.sqrt().sqrt() * 255.0) as u8;
+        image::Luma([l])
+    })
+}
+
+// write picks the output format from the extension of path.
+pub fn write<P: AsRef<Path>>(path: P, matrix: &Matrix) -> Result<()> {
+    use std::fs::File;
+    use std::io::BufWriter;
+
+    let path = path.as_ref();
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("png") => to_image(matrix).save(path).map_err(image_error(path))?,
+        Some("npy") => {
+            let file = File::create(path).map_err(io_error(path))?;
+            write_npy(&mut BufWriter::new(file), matrix)?
+        }
+        Some("csv") => {
+            let file = File::create(path).map_err(io_error(path))?;
+            write_csv(&mut BufWriter::new(file), matrix)?
+        }
+        _ => {
+            return Err(Error::InvalidOutput(format!(
+                "unknown fcgr format for {:?}, use .png, .npy or .csv",
+                path
+            )))
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::temp_database;
+
+    fn database() -> Database {
+        temp_database(2, &[("ac", 1), ("ga", 2), ("tt", 3)])
+    }
+
+    #[test]
+    fn test_matrix() -> Result<()> {
+        let m = database();
+        let matrix = matrix(&m, 2)?;
+        assert_eq!(4, matrix.side);
+        assert_eq!(1, matrix.get(1, 0));
+        assert_eq!(2, matrix.get(0, 2));
+        assert_eq!(3, matrix.get(3, 3));
+        assert_eq!(6, matrix.counts.iter().sum::<u32>());
+        assert!(super::matrix(&m, 3).is_err());
+        assert!(super::matrix(&m, 40).is_err());
+
+        let mut out = Vec::new();
+        write_csv(&mut out, &matrix)?;
+        assert_eq!(
+            "0,1,0,0\n0,0,0,0\n2,0,0,0\n0,0,0,3\n",
+            String::from_utf8(out).unwrap()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_npy() -> Result<()> {
+        let matrix = matrix(&database(), 2)?;
+        let mut out = Vec::new();
+        write_npy(&mut out, &matrix)?;
+        assert_eq!(b"\x93NUMPY\x01\x00", &out[..8]);
+        let header_len = u16::from_le_bytes([out[8], out[9]]) as usize;
+        assert_eq!(0, (10 + header_len) % 64);
+        let header = std::str::from_utf8(&out[10..10 + header_len]).unwrap();
+        assert!(header.contains("'shape': (4, 4)"));
+        assert_eq!(10 + header_len + 16 * 4, out.len());
+        assert_eq!(
+            &[1, 0, 0, 0],
+            &out[10 + header_len + 4..10 + header_len + 8]
+        );
+        Ok(())
+    }
+}
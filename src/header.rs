@@ -0,0 +1,193 @@
+//! The header at the start of an index file. It is a few lines of key=value
+//! text after a magic line, ended by a nul byte and padded with nul bytes so
+//! that the counts after it stay aligned:
+//!
+//! ```text
+//! hustar-index 1
+//! alphabet=dna
+//! symbols=a,c,g,t
+//! seqlen=12
+//! ```
+//!
+//! Spaced seed indexes add a seed=1101101 line, and sampled indexes a
+//! sampling=minimizer:10 line with the order=hash they rank windows by.
+//!
+//! Files written before the header existed start right away with the counts
+//! and open as dna of unknown seqlen.
+
+use crate::alphabet::Alphabet;
+use crate::error::{Error, Result};
+use crate::sample::Sampling;
+use crate::seed::Seed;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+const MAGIC: &[u8] = b"hustar-index 1\n";
+
+// ALIGN is what the header is padded to, which keeps the counts aligned for
+// any integer type.
+const ALIGN: usize = 64;
+
+/// Header describes the layout of the counts of an index file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    pub alphabet: Alphabet,
+    /// seqlen is the longest sequence the index holds, or None for files
+    /// without a header.
+    pub seqlen: Option<usize>,
+    /// seed is the spaced seed the index was built with, if any.
+    pub seed: Option<Seed>,
+    /// sampling is the scheme picking which windows were counted, if any.
+    pub sampling: Option<Sampling>,
+    /// metadata holds every other key of the header.
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl Header {
+    pub fn new(alphabet: Alphabet, seqlen: usize) -> Header {
+        Header {
+            alphabet,
+            seqlen: Some(seqlen),
+            seed: None,
+            sampling: None,
+            metadata: BTreeMap::new(),
+        }
+    }
+
+    // legacy is the header of files written before headers existed.
+    fn legacy() -> Header {
+        Header {
+            alphabet: Alphabet::dna(),
+            seqlen: None,
+            seed: None,
+            sampling: None,
+            metadata: BTreeMap::new(),
+        }
+    }
+
+    /// encode returns the header as written at the start of an index file,
+    /// padded to a multiple of 64 bytes.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = MAGIC.to_vec();
+        let mut line = |key: &str, value: &str| {
+            buf.extend_from_slice(format!("{}={}\n", key, value).as_bytes());
+        };
+        line("alphabet", self.alphabet.name());
+        line("symbols", &self.alphabet.spec());
+        if let Some(seqlen) = self.seqlen {
+            line("seqlen", &seqlen.to_string());
+        }
+        if let Some(seed) = &self.seed {
+            line("seed", &seed.to_string());
+        }
+        if let Some(sampling) = &self.sampling {
+            line("sampling", &sampling.scheme.to_string());
+            line("order", &sampling.order.to_string());
+        }
+        for (key, value) in &self.metadata {
+            line(key, value);
+        }
+        buf.push(0);
+        buf.resize(buf.len().div_ceil(ALIGN) * ALIGN, 0);
+        buf
+    }
+
+    /// decode reads the header from the start of an index file and returns it
+    /// with the offset of the counts. Files without a header decode as dna.
+    pub fn decode(bytes: &[u8]) -> Result<(Header, usize)> {
+        let text = match bytes.strip_prefix(MAGIC) {
+            Some(text) => text,
+            None => return Ok((Header::legacy(), 0)),
+        };
+        let end = text
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or_else(|| Error::InvalidHeader("missing the end of the header".to_string()))?;
+        let len = (MAGIC.len() + end + 1).div_ceil(ALIGN) * ALIGN;
+        let text =
+            std::str::from_utf8(&text[..end]).map_err(|e| Error::InvalidHeader(e.to_string()))?;
+        let mut fields = BTreeMap::new();
+        for line in text.lines() {
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| Error::InvalidHeader(format!("line {:?} is not key=value", line)))?;
+            fields.insert(key.to_string(), value.to_string());
+        }
+        let mut take = |key: &str| {
+            fields
+                .remove(key)
+                .ok_or_else(|| Error::InvalidHeader(format!("missing {}", key)))
+        };
+        let name = take("alphabet")?;
+        let spec = take("symbols")?;
+        let seqlen = take("seqlen")?
+            .parse()
+            .map_err(|e| Error::InvalidHeader(format!("seqlen: {}", e)))?;
+        let seed = fields.remove("seed").map(|s| s.parse()).transpose()?;
+        let sampling = match (fields.remove("sampling"), fields.remove("order")) {
+            (Some(scheme), order) => Some(Sampling {
+                scheme: scheme.parse()?,
+                order: order.as_deref().unwrap_or("lexicographic").parse()?,
+            }),
+            (None, _) => None,
+        };
+        let groups: Vec<&str> = spec.split(',').collect();
+        let header = Header {
+            alphabet: Alphabet::new(&name, &groups)?,
+            seqlen: Some(seqlen),
+            seed,
+            sampling,
+            metadata: fields,
+        };
+        Ok((header, len))
+    }
+
+    /// window_len is the length of the windows that keys of len are read from.
+    pub fn window_len(&self, len: usize) -> usize {
+        match &self.seed {
+            Some(seed) => seed.window_len(len),
+            None => len,
+        }
+    }
+
+    /// key turns a full window of the seed into the key the index stores.
+    /// Anything else, including every sequence of an unseeded index, is
+    /// already a key. A full window is never mistaken for a key, since the
+    /// ignored positions make it longer than the longest key.
+    pub fn key<'a>(&self, seq: &'a str) -> Cow<'a, str> {
+        match self.seed.as_ref().and_then(|seed| seed.key(seq)) {
+            Some(key) => Cow::Owned(key),
+            None => Cow::Borrowed(seq),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_header() -> Result<()> {
+        let mut header = Header::new(Alphabet::purine_pyrimidine(), 7);
+        header.seed = Some("10111".parse()?);
+        header.sampling = Some(Sampling {
+            scheme: "minimizer:4".parse()?,
+            order: "hash".parse()?,
+        });
+        header.metadata.insert("note".to_string(), "x".to_string());
+        let buf = header.encode();
+        assert_eq!(0, buf.len() % ALIGN);
+        assert!(buf.starts_with(b"hustar-index 1\nalphabet=purine-pyrimidine\n"));
+        assert_eq!((header.clone(), buf.len()), Header::decode(&buf)?);
+        assert_eq!("ryry", header.key("rgyry"));
+        assert_eq!("ryr", header.key("ryr"));
+
+        let (legacy, offset) = Header::decode(&[0; 16])?;
+        assert_eq!(
+            (Alphabet::dna(), None, 0),
+            (legacy.alphabet, legacy.seqlen, offset)
+        );
+        assert!(Header::decode(b"hustar-index 1\nalphabet=dna\n").is_err());
+        Ok(())
+    }
+}
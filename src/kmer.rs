@@ -0,0 +1,300 @@
+//! A k-mer packed 2 bits per nucleotide.
+
+use crate::alphabet;
+use crate::error::{Error, Result};
+use std::fmt;
+use std::str::FromStr;
+
+/// RADIX is the number of nucleotides a Kmer packs.
+pub const RADIX: usize = 4;
+
+/// MAX_LEN is the longest k-mer that fits the packed representation.
+pub const MAX_LEN: usize = 64;
+
+/// MAX_INDEX_LEN is the longest k-mer whose position in a dense index, which
+/// holds every shorter sequence before it, still fits a usize.
+pub const MAX_INDEX_LEN: usize = (usize::BITS / 2 - 1) as usize;
+
+/// Kmer is a sequence of up to MAX_LEN nucleotides packed as a=00, c=01, g=10
+/// and t=11, first nucleotide most significant. Its index is its position in
+/// the index file, where all k-mers of one length follow the shorter ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Kmer {
+    addr: u128,
+    len: usize,
+}
+
+/// check_index_len fails for lengths a dense index cannot hold.
+pub fn check_index_len(seqlen: usize) -> Result<()> {
+    if seqlen > MAX_INDEX_LEN {
+        return Err(Error::UnsupportedSeqlen {
+            seqlen,
+            max: MAX_INDEX_LEN,
+            backend: "dense index",
+        });
+    }
+    Ok(())
+}
+
+impl Kmer {
+    /// new builds a k-mer from its packed address. Bits past len are dropped.
+    pub fn new(addr: u128, len: usize) -> Kmer {
+        Kmer {
+            addr: addr & mask(len),
+            len,
+        }
+    }
+
+    /// from_seq packs an acgt sequence in either case.
+    pub fn from_seq(seq: &str) -> Result<Kmer> {
+        if seq.len() > MAX_LEN {
+            return Err(Error::TooLong {
+                seq: seq.to_string(),
+                len: seq.len(),
+                max: MAX_LEN,
+            });
+        }
+        let mut addr = 0;
+        for l in seq.chars() {
+            let bits = u8::try_from(l)
+                .ok()
+                .and_then(nucleotide_bits)
+                .ok_or_else(|| Error::InvalidNucleotide {
+                    letter: l,
+                    seq: seq.to_string(),
+                })?;
+            addr = (addr << 2) | bits;
+        }
+        Ok(Kmer {
+            addr,
+            len: seq.len(),
+        })
+    }
+
+    /// from_index returns the k-mer stored at index of the index file.
+    pub fn from_index(index: usize) -> Kmer {
+        let mut len = 1;
+        while len < MAX_INDEX_LEN && index >= alphabet::base_index(RADIX, len + 1) {
+            len += 1;
+        }
+        Kmer {
+            addr: (index - alphabet::base_index(RADIX, len)) as u128,
+            len,
+        }
+    }
+
+    /// addr is the packed k-mer without its length.
+    pub fn addr(&self) -> u128 {
+        self.addr
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// try_index is the position of the k-mer's count in a dense index file,
+    /// or an error for k-mers past MAX_INDEX_LEN.
+    pub fn try_index(&self) -> Result<usize> {
+        check_index_len(self.len)?;
+        Ok(alphabet::base_index(RADIX, self.len) + self.addr as usize)
+    }
+
+    /// index is try_index for k-mers known to fit, and panics otherwise.
+    pub fn index(&self) -> usize {
+        self.try_index().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// revcomp returns the reverse complement, which reads the other strand.
+    pub fn revcomp(&self) -> Kmer {
+        // complementing flips both bits: a=00 <-> t=11 and c=01 <-> g=10.
+        let mut addr = !self.addr;
+        let mut rev = 0;
+        for _ in 0..self.len {
+            rev = (rev << 2) | (addr & 0b11);
+            addr >>= 2;
+        }
+        Kmer::new(rev, self.len)
+    }
+
+    /// canonical returns the smaller of the k-mer and its reverse complement,
+    /// so that both strands count as one.
+    pub fn canonical(&self) -> Kmer {
+        (*self).min(self.revcomp())
+    }
+
+    /// all iterates every k-mer of length len in index order.
+    pub fn all(len: usize) -> impl Iterator<Item = Kmer> {
+        (0..=mask(len)).map(move |addr| Kmer { addr, len })
+    }
+
+    /// windows iterates the start and k-mer of every window of length len in
+    /// seq, skipping windows holding anything but acgt. Every k-mer is rolled
+    /// from the previous one instead of being parsed again.
+    pub fn windows(seq: &[u8], len: usize) -> Windows<'_> {
+        Windows {
+            seq,
+            len,
+            pos: 0,
+            valid: 0,
+            addr: 0,
+        }
+    }
+}
+
+// mask covers the bits of a k-mer of length len.
+fn mask(len: usize) -> u128 {
+    if len >= MAX_LEN {
+        u128::MAX
+    } else {
+        (1 << (2 * len)) - 1
+    }
+}
+
+fn nucleotide_bits(l: u8) -> Option<u128> {
+    match l.to_ascii_lowercase() {
+        b'a' => Some(0b00),
+        b'c' => Some(0b01),
+        b'g' => Some(0b10),
+        b't' => Some(0b11),
+        _ => None,
+    }
+}
+
+impl FromStr for Kmer {
+    type Err = Error;
+    fn from_str(seq: &str) -> Result<Kmer> {
+        Kmer::from_seq(seq)
+    }
+}
+
+/// Windows is the iterator returned by Kmer::windows.
+pub struct Windows<'a> {
+    seq: &'a [u8],
+    len: usize,
+    pos: usize,
+    // valid counts the acgt nucleotides in a row ending before pos.
+    valid: usize,
+    addr: u128,
+}
+
+impl<'a> Iterator for Windows<'a> {
+    type Item = (usize, Kmer);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pos < self.seq.len() {
+            let l = self.seq[self.pos];
+            self.pos += 1;
+            match nucleotide_bits(l) {
+                Some(bits) => {
+                    self.addr = ((self.addr << 2) | bits) & mask(self.len);
+                    self.valid += 1;
+                }
+                None => {
+                    self.valid = 0;
+                    continue;
+                }
+            }
+            if self.len > 0 && self.valid >= self.len {
+                return Some((self.pos - self.len, Kmer::new(self.addr, self.len)));
+            }
+        }
+        None
+    }
+}
+
+impl fmt::Display for Kmer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for i in (0..self.len).rev() {
+            let l = b"acgt"[((self.addr >> (2 * i)) & 0b11) as usize];
+            write!(f, "{}", l as char)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_kmer() -> Result<()> {
+        let k = Kmer::from_seq("ACgt")?;
+        assert_eq!((0b00011011, 4), (k.addr(), k.len()));
+        assert_eq!("acgt", k.to_string());
+        assert_eq!(k, Kmer::from_index(k.index()));
+        assert_eq!(0, Kmer::from_seq("a")?.index());
+        assert_eq!(4, Kmer::from_seq("aa")?.index());
+        assert!(matches!(
+            Kmer::from_seq("acnt"),
+            Err(Error::InvalidNucleotide { letter: 'n', .. })
+        ));
+        assert!(matches!(
+            Kmer::from_seq(&"a".repeat(65)),
+            Err(Error::TooLong { len: 65, .. })
+        ));
+        for index in 0..340 {
+            assert_eq!(index, Kmer::from_index(index).index());
+        }
+        assert_eq!(k, "acgt".parse()?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_long() -> Result<()> {
+        let seq = "acgttgca".repeat(8);
+        let k: Kmer = seq.parse()?;
+        assert_eq!((64, seq.clone()), (k.len(), k.to_string()));
+        assert_eq!(k, k.revcomp().revcomp());
+        let windows: Vec<Kmer> = Kmer::windows(seq.as_bytes(), 40).map(|(_, k)| k).collect();
+        assert_eq!(25, windows.len());
+        assert_eq!(&seq[24..], windows[24].to_string());
+
+        assert!(k.try_index().is_err());
+        let longest: Kmer = "t".repeat(MAX_INDEX_LEN).parse()?;
+        assert_eq!(
+            alphabet::base_index(RADIX, MAX_INDEX_LEN + 1) - 1,
+            longest.try_index()?
+        );
+        assert!(matches!(
+            "a".repeat(MAX_INDEX_LEN + 1).parse::<Kmer>()?.try_index(),
+            Err(Error::UnsupportedSeqlen { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_revcomp() -> Result<()> {
+        let k: Kmer = "aacg".parse()?;
+        assert_eq!("cgtt", k.revcomp().to_string());
+        assert_eq!(k, k.revcomp().revcomp());
+        assert_eq!(k, k.canonical());
+        assert_eq!(k, k.revcomp().canonical());
+        let long: Kmer = "acgt".repeat(8).parse()?;
+        assert_eq!(long, long.revcomp());
+        Ok(())
+    }
+
+    #[test]
+    fn test_all() {
+        let all: Vec<String> = Kmer::all(2).map(|k| k.to_string()).collect();
+        assert_eq!(16, all.len());
+        assert_eq!(("aa", "ac", "tt"), (&all[0][..], &all[1][..], &all[15][..]));
+        assert!(Kmer::all(3).enumerate().all(|(i, k)| k.addr() == i as u128));
+    }
+
+    #[test]
+    fn test_windows() {
+        let windows: Vec<(usize, String)> = Kmer::windows(b"ACGTNacgTa", 3)
+            .map(|(i, k)| (i, k.to_string()))
+            .collect();
+        let expected = [(0, "acg"), (1, "cgt"), (5, "acg"), (6, "cgt"), (7, "gta")];
+        let expected: Vec<(usize, String)> =
+            expected.iter().map(|(i, s)| (*i, s.to_string())).collect();
+        assert_eq!(expected, windows);
+        assert_eq!(0, Kmer::windows(b"ac", 3).count());
+    }
+}
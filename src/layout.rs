@@ -0,0 +1,311 @@
+// layout decides which range of the index every pixel of an image covers. The
+// radial star of traverse is one layout, the others lay out the sequences of a
+// ring on a square or on horizontal bands. Every layout hands out pixels whose
+// index range is contiguous so the accumulator pipeline can sum them.
+
+use crate::alphabet::base_index;
+use crate::kmer::RADIX;
+use crate::traverse::{make_points, make_supersampled_points, Point, Sector};
+use clap::ArgEnum;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Pixel {
+    pub w: u32,
+    pub h: u32,
+    pub seqlen: usize,
+    // gte and lt are the [gte, lt) range of the index under the pixel.
+    pub gte: usize,
+    pub lt: usize,
+}
+
+impl From<&Point> for Pixel {
+    fn from(p: &Point) -> Self {
+        let (gte, lt) = p.index_range();
+        Pixel {
+            w: p.w,
+            h: p.h,
+            seqlen: p.seqlen,
+            gte,
+            lt,
+        }
+    }
+}
+
+pub trait Layout {
+    // pixels returns the pixels of a width x height image, sorted by index so
+    // that an accumulator walking them mostly moves forward.
+    fn pixels(&self, width: u32, height: u32) -> Vec<Pixel>;
+}
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq)]
+pub enum LayoutKind {
+    Radial,
+    Fcgr,
+    Hilbert,
+    Bands,
+}
+
+// make_layout builds a layout of the given kind over the sector. Square layouts
+// only show the ring of sector.max_seqlen and ignore samples, since their cells
+// never split a pixel.
+pub fn make_layout(kind: LayoutKind, sector: Sector, samples: u32) -> Box<dyn Layout> {
+    match kind {
+        LayoutKind::Radial => Box::new(Radial { sector, samples }),
+        LayoutKind::Fcgr => Box::new(Fcgr {
+            seqlen: sector.max_seqlen,
+        }),
+        LayoutKind::Hilbert => Box::new(Hilbert {
+            seqlen: sector.max_seqlen,
+        }),
+        LayoutKind::Bands => Box::new(Bands {
+            min_seqlen: sector.min_seqlen,
+            max_seqlen: sector.max_seqlen,
+        }),
+    }
+}
+
+fn sort_pixels(pixels: &mut [Pixel]) {
+    pixels.sort_by_key(|p| (p.gte, p.lt));
+}
+
+// Radial is the star: angle is the position of a sequence within its ring and
+// radius is the seqlen. With samples > 1 every pixel is covered by samples^2
+// sub pixels, so that pixels straddling a ring or wedge boundary blend both.
+pub struct Radial {
+    pub sector: Sector,
+    pub samples: u32,
+}
+
+impl Layout for Radial {
+    fn pixels(&self, width: u32, height: u32) -> Vec<Pixel> {
+        let points = if self.samples > 1 {
+            make_supersampled_points(width, height, self.sector, self.samples)
+        } else {
+            make_points(width, height, self.sector)
+        };
+        points.iter().map(Pixel::from).collect()
+    }
+}
+
+// square_pixels lays out the ring of seqlen on a square grid of cells that is
+// as fine as the image allows without going past one sequence per cell. cell
+// maps the column and row of a cell on a grid of side 2^order to its position
+// along the ring. Cells of a coarser grid cover every sequence sharing a prefix,
+// so their index range stays contiguous.
+fn square_pixels<F>(width: u32, height: u32, seqlen: usize, cell: F) -> Vec<Pixel>
+where
+    F: Fn(u64, u64, u32) -> u64,
+{
+    let side = width.min(height).max(1);
+    let order = (31 - side.leading_zeros()).min(seqlen as u32);
+    let cells = 1_u64 << order;
+    let shift = 2 * (seqlen as u32 - order);
+    let base = base_index(RADIX, seqlen);
+    let mut pixels = Vec::with_capacity(width as usize * height as usize);
+    for w in 0..width {
+        for h in 0..height {
+            let col = w as u64 * cells / width as u64;
+            let row = h as u64 * cells / height as u64;
+            let addr = cell(col, row, order) << shift;
+            pixels.push(Pixel {
+                w,
+                h,
+                seqlen,
+                gte: base + addr as usize,
+                lt: base + (addr + (1 << shift)) as usize,
+            });
+        }
+    }
+    sort_pixels(&mut pixels);
+    pixels
+}
+
+// Fcgr is the frequency chaos game representation of one seqlen. Every
+// nucleotide of the 2-bit address contributes its low bit to the column and its
+// high bit to the row, first nucleotide most significant, which puts a, c, g and
+// t in the top left, top right, bottom left and bottom right quadrants.
+pub struct Fcgr {
+    pub seqlen: usize,
+}
+
+// interleave builds a 2-bit address from order bits of col and row.
+pub fn interleave(col: u64, row: u64, order: u32) -> u64 {
+    let mut addr = 0;
+    for i in (0..order).rev() {
+        addr = (addr << 2) | (((row >> i) & 1) << 1) | ((col >> i) & 1);
+    }
+    addr
+}
+
+// deinterleave is the inverse of interleave and returns (col, row).
+pub fn deinterleave(addr: u64, order: u32) -> (u64, u64) {
+    let (mut col, mut row) = (0, 0);
+    for i in (0..order).rev() {
+        let pair = (addr >> (2 * i)) & 0b11;
+        col = (col << 1) | (pair & 1);
+        row = (row << 1) | (pair >> 1);
+    }
+    (col, row)
+}
+
+impl Layout for Fcgr {
+    fn pixels(&self, width: u32, height: u32) -> Vec<Pixel> {
+        square_pixels(width, height, self.seqlen, interleave)
+    }
+}
+
+// Hilbert lays the ring of one seqlen along a Hilbert curve, so that sequences
+// that are lexicographic neighbours stay spatial neighbours.
+pub struct Hilbert {
+    pub seqlen: usize,
+}
+
+// hilbert_index returns the distance along a Hilbert curve filling a grid of
+// side 2^order of the cell at (col, row).
+pub fn hilbert_index(col: u64, row: u64, order: u32) -> u64 {
+    let n = 1_u64 << order;
+    let (mut x, mut y) = (col, row);
+    let mut d = 0;
+    let mut s = n >> 1;
+    while s > 0 {
+        let rx = ((x & s) > 0) as u64;
+        let ry = ((y & s) > 0) as u64;
+        d += s * s * ((3 * rx) ^ ry);
+        if ry == 0 {
+            if rx == 1 {
+                x = n - 1 - x;
+                y = n - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s >>= 1;
+    }
+    d
+}
+
+impl Layout for Hilbert {
+    fn pixels(&self, width: u32, height: u32) -> Vec<Pixel> {
+        square_pixels(width, height, self.seqlen, hilbert_index)
+    }
+}
+
+// Bands stacks one horizontal band per seqlen from top to bottom. Every band
+// runs through its ring from left to right.
+pub struct Bands {
+    pub min_seqlen: usize,
+    pub max_seqlen: usize,
+}
+
+impl Layout for Bands {
+    fn pixels(&self, width: u32, height: u32) -> Vec<Pixel> {
+        let bands = (self.max_seqlen - self.min_seqlen + 1) as u64;
+        let mut pixels = Vec::with_capacity(width as usize * height as usize);
+        for h in 0..height {
+            let seqlen = self.min_seqlen + (h as u64 * bands / height as u64) as usize;
+            let max = 1_u128 << (2 * seqlen);
+            let base = base_index(RADIX, seqlen);
+            for w in 0..width {
+                let gte = (max * w as u128 / width as u128) as usize;
+                let lt = (max * (w as u128 + 1) / width as u128) as usize;
+                pixels.push(Pixel {
+                    w,
+                    h,
+                    seqlen,
+                    gte: base + gte,
+                    lt: base + lt.max(gte + 1),
+                });
+            }
+        }
+        sort_pixels(&mut pixels);
+        pixels
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::index_to_seq;
+
+    fn find(pixels: &[Pixel], w: u32, h: u32) -> Pixel {
+        *pixels.iter().find(|p| p.w == w && p.h == h).unwrap()
+    }
+
+    #[test]
+    fn test_interleave() {
+        assert_eq!(0b1001, interleave(0b01, 0b10, 2));
+        assert_eq!(0b1111, interleave(0b11, 0b11, 2));
+        assert_eq!(0b000100, interleave(0b010, 0b000, 3));
+        for addr in 0..256 {
+            let (col, row) = deinterleave(addr, 4);
+            assert_eq!(addr, interleave(col, row, 4));
+        }
+    }
+
+    #[test]
+    fn test_hilbert_index() {
+        let order = 3;
+        let mut seen = [false; 64];
+        let mut cells = vec![(0, 0); 64];
+        for col in 0..8 {
+            for row in 0..8 {
+                let d = hilbert_index(col, row, order) as usize;
+                assert!(!seen[d]);
+                seen[d] = true;
+                cells[d] = (col as i64, row as i64);
+            }
+        }
+        // neighbours along the curve are neighbours on the grid.
+        for d in 1..64 {
+            let (a, b) = (cells[d - 1], cells[d]);
+            assert_eq!(1, (a.0 - b.0).abs() + (a.1 - b.1).abs());
+        }
+    }
+
+    #[test]
+    fn test_fcgr() {
+        let pixels = Fcgr { seqlen: 2 }.pixels(4, 4);
+        assert_eq!(16, pixels.len());
+        assert_eq!("aa", index_to_seq(find(&pixels, 0, 0).gte));
+        assert_eq!("ct", index_to_seq(find(&pixels, 3, 1).gte));
+        assert_eq!("tt", index_to_seq(find(&pixels, 3, 3).gte));
+
+        // a coarser image covers whole prefixes.
+        let p = find(&Fcgr { seqlen: 3 }.pixels(2, 2), 1, 0);
+        assert_eq!(base_index(RADIX, 3) + 16, p.gte);
+        assert_eq!(base_index(RADIX, 3) + 32, p.lt);
+    }
+
+    #[test]
+    fn test_bands() {
+        let pixels = Bands {
+            min_seqlen: 1,
+            max_seqlen: 2,
+        }
+        .pixels(8, 4);
+        let p = find(&pixels, 0, 0);
+        assert_eq!((1, 0, 1), (p.seqlen, p.gte, p.lt));
+        let p = find(&pixels, 1, 1);
+        assert_eq!((1, 0, 1), (p.seqlen, p.gte, p.lt));
+        let p = find(&pixels, 7, 3);
+        assert_eq!((2, 18, 20), (p.seqlen, p.gte, p.lt));
+        assert!(pixels.windows(2).all(|w| w[0].gte <= w[1].gte));
+    }
+
+    #[test]
+    fn test_radial() {
+        let pixels = Radial {
+            sector: Sector::full(3),
+            samples: 1,
+        }
+        .pixels(16, 16);
+        assert_eq!(256, pixels.len());
+        assert!(pixels.iter().all(|p| p.gte < p.lt));
+
+        let pixels = Radial {
+            sector: Sector::full(3),
+            samples: 2,
+        }
+        .pixels(16, 16);
+        assert_eq!(4 * 256, pixels.len());
+    }
+}
@@ -0,0 +1,49 @@
+//! hustar builds an index of the counts of every subsequence of a genome up to
+//! a length and renders it as a star, where rings hold sequence lengths and
+//! angles the sequences of a ring.
+//!
+//! [`database`] opens and builds indexes whose [`header`] records their
+//! [`alphabet`], [`kmer`] packs dna sequences, and [`accumulator`] and
+//! [`traverse`] sum ranges of an index for rendering. [`markov`] derives
+//! Markov chains from the counts of an index, [`sketch`] estimates counts of
+//! seqlens too long for an index and [`presence`] keeps a bit per sequence.
+//! The remaining modules
+//! implement the renderers of the command line tool.
+
+pub mod accumulator;
+pub mod alphabet;
+pub mod animate;
+pub mod annotate;
+pub mod build;
+pub mod database;
+pub mod diff;
+pub mod distance;
+pub mod enrichment;
+pub mod error;
+pub mod fcgr;
+pub mod header;
+pub mod kmer;
+pub mod layout;
+pub mod markov;
+pub mod motif;
+pub mod positions;
+pub mod presence;
+pub mod query;
+pub mod render;
+pub mod sample;
+pub mod scan;
+pub mod seed;
+pub mod sketch;
+pub mod svg;
+pub mod tiles;
+pub mod traverse;
+pub mod view;
+
+pub use accumulator::Accumulator;
+pub use alphabet::Alphabet;
+pub use database::{CountSource, Database, DatabaseMut};
+pub use error::{Error, Result};
+pub use header::Header;
+pub use kmer::Kmer;
+pub use seed::Seed;
+pub use traverse::Point;
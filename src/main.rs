@@ -1,25 +1,15 @@
-mod accumulator;
-mod database;
-mod traverse;
-
 use anyhow::Result;
-use bio::io::fasta::Reader;
-use crossbeam::channel::bounded;
-use crossbeam::channel::unbounded;
-use image::ImageBuffer;
-
-use std::path::PathBuf;
-use std::thread::sleep;
-use std::time::Duration;
-use std::time::Instant;
-use std::{str, thread, time};
+use hustar::{
+    animate, annotate, build, database, diff, distance, enrichment, fcgr, layout, motif, query,
+    render, scan, svg, tiles, view,
+};
 use tracing::info;
 
 use clap::{Parser, Subcommand};
-use std::sync::mpsc::channel;
+use hustar::markov::Markov;
+use hustar::CountSource;
 
-use crate::accumulator::Accumulator;
-use crate::traverse::make_points;
+use hustar::traverse::{make_points, Sector};
 
 #[derive(Parser)]
 #[clap(name = "hustar")]
@@ -36,264 +26,1158 @@ enum Commands {
         fasta_file: String,
         index_file: String,
         sequence_length: usize,
+        // alphabet is dna, rna, protein, purine-pyrimidine or the comma
+        // separated letters of every symbol, such as "ag,ct".
+        #[clap(long, default_value = "dna")]
+        alphabet: String,
+        // seed only counts the care positions of a spaced seed such as
+        // 1101101 in every window. sequence_length may not exceed the number
+        // of care positions.
+        #[clap(long)]
+        seed: Option<String>,
+        // sampling only counts minimizer:W, open-syncmer:S or
+        // closed-syncmer:S windows of every record, ranked by order.
+        #[clap(long, conflicts_with = "seed")]
+        sampling: Option<String>,
+        #[clap(long, default_value = "lexicographic")]
+        order: String,
+        // positions also writes <index_file>.pos, which lists where every
+        // sequence occurs for locate.
+        #[clap(long)]
+        positions: bool,
+    },
+    // Locate prints every occurrence of the sequences as bed lines of record,
+    // start, end and sequence. It needs an index built with --positions.
+    #[clap(arg_required_else_help = true)]
+    Locate {
+        index_file: String,
+        #[clap(required = true)]
+        sequences: Vec<String>,
+    },
+    // Scan writes the count of the sequence_length long window starting at
+    // every position of every record of fasta_file as a bedGraph or wiggle
+    // track.
+    #[clap(arg_required_else_help = true)]
+    Scan {
+        index_file: String,
+        fasta_file: String,
+        sequence_length: usize,
+        #[clap(long, arg_enum, default_value = "bedgraph")]
+        format: scan::TrackFormat,
+        // output is written to stdout unless given.
+        #[clap(long)]
+        output: Option<String>,
+    },
+    // Query reports how many of the sequence_length long windows of every
+    // record of fasta_file occur in the index, and how often.
+    #[clap(arg_required_else_help = true)]
+    Query {
+        index_file: String,
+        fasta_file: String,
+        sequence_length: usize,
+        #[clap(long, arg_enum, default_value = "tsv")]
+        format: query::ReportFormat,
+        // output is written to stdout unless given.
+        #[clap(long)]
+        output: Option<String>,
+    },
+    // Markov derives a chain of order from the index, adding pseudocount to
+    // the count of every transition.
+    #[clap(arg_required_else_help = true)]
+    Markov {
+        index_file: String,
+        order: usize,
+        #[clap(long, default_value = "1")]
+        pseudocount: f64,
+        #[clap(subcommand)]
+        action: MarkovAction,
+    },
+    // Enrichment writes the observed over the expected count of every
+    // sequence of sequence_length to <output>.tsv, expected by a Markov chain
+    // of order. With side_length the star colored by enrichment is written to
+    // <output>.png.
+    #[clap(arg_required_else_help = true)]
+    Enrichment {
+        index_file: String,
+        sequence_length: usize,
+        #[clap(long, default_value = "1")]
+        order: usize,
+        #[clap(long)]
+        side_length: Option<usize>,
+        #[clap(long, default_value = "out")]
+        output: String,
+    },
+    // Distance writes the distance of every pair of indexes over their rings
+    // of sequence_length to <output>.tsv, and with tree their neighbor joining
+    // tree to <output>.nwk.
+    #[clap(arg_required_else_help = true)]
+    Distance {
+        #[clap(required = true)]
+        index_files: Vec<String>,
+        #[clap(long)]
+        sequence_length: usize,
+        #[clap(long, arg_enum, default_value = "jensen-shannon")]
+        metric: distance::Metric,
+        #[clap(long)]
+        tree: bool,
+        #[clap(long, default_value = "out")]
+        output: String,
+    },
+    // Sketch estimates counts of sequences too long for an index in a
+    // Count-Min sketch.
+    #[clap(arg_required_else_help = true)]
+    Sketch {
+        #[clap(subcommand)]
+        action: SketchAction,
+    },
+    // Presence keeps one bit per sequence telling whether it occurs.
+    #[clap(arg_required_else_help = true)]
+    Presence {
+        #[clap(subcommand)]
+        action: PresenceAction,
     },
     #[clap(arg_required_else_help = true)]
     Visualize {
+        // index_file may also be a sketch, whose estimates are drawn. Every
+        // sequence of every ring is estimated, so sketches past the seqlens an
+        // index could hold render slowly.
+        index_file: String,
+        sequence_length: usize,
+        side_length: usize,
+        // prefix zooms into the sector of sequences starting with it.
+        #[clap(long, conflicts_with_all = &["theta-gte", "theta-lt"])]
+        prefix: Option<String>,
+        #[clap(long)]
+        theta_gte: Option<f64>,
+        #[clap(long)]
+        theta_lt: Option<f64>,
+        #[clap(long)]
+        min_seqlen: Option<usize>,
+        // layout picks how sequences are placed on the image. Square layouts
+        // only show sequence_length.
+        #[clap(long, arg_enum, default_value = "radial")]
+        layout: layout::LayoutKind,
+        // samples supersamples every pixel of the radial layout samples x
+        // samples times to smooth ring and wedge edges.
+        #[clap(long, default_value = "1")]
+        samples: u32,
+        // annotate adds ring boundaries, quadrant labels, a colorbar and a
+        // title to the image.
+        #[clap(long)]
+        annotate: bool,
+        // motif highlights every pixel holding a sequence that matches the
+        // IUPAC pattern, or the regex when prefixed with "re:". Repeatable.
+        #[clap(long = "motif", multiple_occurrences = true)]
+        motifs: Vec<String>,
+        #[clap(long, arg_enum, default_value = "contains")]
+        motif_mode: motif::MatchMode,
+    },
+    #[clap(arg_required_else_help = true)]
+    Svg {
+        index_file: String,
+        sequence_length: usize,
+        side_length: usize,
+        #[clap(long, default_value = "4096")]
+        max_wedges: usize,
+        #[clap(long, default_value = "out.svg")]
+        output: String,
+        #[clap(long)]
+        annotate: bool,
+        #[clap(long = "motif", multiple_occurrences = true)]
+        motifs: Vec<String>,
+        #[clap(long, arg_enum, default_value = "contains")]
+        motif_mode: motif::MatchMode,
+    },
+    #[clap(arg_required_else_help = true)]
+    Diff {
+        index_file_a: String,
+        index_file_b: String,
+        sequence_length: usize,
+        side_length: usize,
+        #[clap(long, arg_enum, default_value = "log-ratio")]
+        metric: diff::Metric,
+        // top is the number of most differing sequences to report.
+        #[clap(long, default_value = "50")]
+        top: usize,
+        #[clap(long, default_value = "out")]
+        output: String,
+    },
+    // Animate grows the star from seqlen 1 to sequence_length when given one
+    // index, or shows one frame per index when given several.
+    #[clap(arg_required_else_help = true)]
+    Animate {
+        #[clap(required = true)]
+        index_files: Vec<String>,
+        #[clap(long)]
+        sequence_length: usize,
+        #[clap(long)]
+        side_length: usize,
+        // output is a gif if it ends in .gif and a directory of frames otherwise.
+        #[clap(long, default_value = "out.gif")]
+        output: String,
+        #[clap(long, default_value = "500")]
+        delay_ms: u32,
+    },
+    #[clap(arg_required_else_help = true)]
+    Fcgr {
+        index_file: String,
+        sequence_length: usize,
+        // output is written as png, npy or csv depending on its extension.
+        output: String,
+    },
+    #[clap(arg_required_else_help = true)]
+    Tiles {
+        index_file: String,
+        sequence_length: usize,
+        output_dir: String,
+        #[clap(long, arg_enum, default_value = "xyz")]
+        format: tiles::TileFormat,
+        #[clap(long, default_value = "256")]
+        tile_size: u32,
+        // max_zoom is the deepest level, which is tile_size * 2^max_zoom wide.
+        #[clap(long, default_value = "4")]
+        max_zoom: u32,
+    },
+    #[clap(arg_required_else_help = true)]
+    View {
         index_file: String,
         sequence_length: usize,
         side_length: usize,
+        #[clap(long, default_value = "1")]
+        zoom: u32,
+        #[clap(long, default_value = "0")]
+        left: u32,
+        #[clap(long, default_value = "0")]
+        top: u32,
+        // dump writes the view to this file instead of opening a window.
+        #[clap(long)]
+        dump: Option<String>,
     },
 }
 
-fn filter_n(seq: &&[u8]) -> bool {
-    for l in seq.iter() {
-        let l = l.to_ascii_lowercase();
-        match l {
-            b'n' => return false,
-            b'm' => return false,
-            b'r' => return false,
-            b'y' => return false,
-            b'w' => return false,
-            b'k' => return false,
-            b'b' => return false,
-            b's' => return false,
-            _ => continue,
-        }
-    }
-    true
+#[derive(Subcommand)]
+enum MarkovAction {
+    // Export writes the probability of every symbol after every context as
+    // tsv.
+    Export {
+        // output is written to stdout unless given.
+        #[clap(long)]
+        output: Option<String>,
+    },
+    // Score writes the log likelihood of every record of fasta_file as tsv, in
+    // total and per scored position.
+    Score {
+        fasta_file: String,
+        #[clap(long)]
+        output: Option<String>,
+    },
+    // Generate writes count random records of length drawn from the chain as
+    // fasta. Record i is generated from seed + i.
+    Generate {
+        length: usize,
+        #[clap(long, default_value = "1")]
+        count: usize,
+        #[clap(long, default_value = "0")]
+        seed: u64,
+        #[clap(long)]
+        output: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum SketchAction {
+    // Build counts every sequence of length 1..=sequence_length of fasta_file
+    // into depth rows of width counters.
+    #[clap(arg_required_else_help = true)]
+    Build {
+        fasta_file: String,
+        sketch_file: String,
+        sequence_length: usize,
+        #[clap(long, default_value = "dna")]
+        alphabet: String,
+        #[clap(long, default_value = "1048576")]
+        width: usize,
+        #[clap(long, default_value = "4")]
+        depth: usize,
+    },
+    // Get prints the estimated count of every sequence.
+    #[clap(arg_required_else_help = true)]
+    Get {
+        sketch_file: String,
+        #[clap(required = true)]
+        sequences: Vec<String>,
+    },
+    // Export writes the estimates of every sequence of length
+    // 1..=sequence_length as an index, which renders as an approximate star.
+    #[clap(arg_required_else_help = true)]
+    Export {
+        sketch_file: String,
+        index_file: String,
+        sequence_length: usize,
+    },
+}
+
+#[derive(Subcommand)]
+enum PresenceAction {
+    // Build marks every sequence of length 1..=sequence_length of fasta_file
+    // without counting them.
+    #[clap(arg_required_else_help = true)]
+    Build {
+        fasta_file: String,
+        presence_file: String,
+        sequence_length: usize,
+        #[clap(long, default_value = "dna")]
+        alphabet: String,
+    },
+    // Derive marks the sequences an index counted.
+    #[clap(arg_required_else_help = true)]
+    Derive {
+        index_file: String,
+        presence_file: String,
+    },
+    // Get prints whether every sequence occurs.
+    #[clap(arg_required_else_help = true)]
+    Get {
+        presence_file: String,
+        #[clap(required = true)]
+        sequences: Vec<String>,
+    },
+    // Rings prints how many sequences of every length occur.
+    #[clap(arg_required_else_help = true)]
+    Rings { presence_file: String },
 }
 
 fn main() {
-    tracing_subscriber::fmt::init();
+    // logs go to stderr so that locate and scan can write to stdout.
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
     let args = Cli::parse();
     match &args.command {
         Commands::Build {
             fasta_file,
             index_file,
             sequence_length,
+            alphabet,
+            seed,
+            sampling,
+            order,
+            positions,
         } => {
-            create(fasta_file, index_file, *sequence_length);
+            let alphabet = alphabet.parse().expect("while parsing alphabet");
+            let header = hustar::Header {
+                seed: seed
+                    .as_ref()
+                    .map(|s| s.parse())
+                    .transpose()
+                    .expect("while parsing seed"),
+                sampling: sampling.as_ref().map(|s| hustar::sample::Sampling {
+                    scheme: s.parse().expect("while parsing sampling"),
+                    order: order.parse().expect("while parsing order"),
+                }),
+                ..hustar::Header::new(alphabet, *sequence_length)
+            };
+            build::build(fasta_file, index_file, header).expect("while building index");
+            if *positions {
+                hustar::positions::build(fasta_file, index_file).expect("while building positions");
+            }
+        }
+        Commands::Locate {
+            index_file,
+            sequences,
+        } => {
+            locate(index_file, sequences).expect("while locating");
+        }
+        Commands::Scan {
+            index_file,
+            fasta_file,
+            sequence_length,
+            format,
+            output,
+        } => {
+            let m = database::Database::open(index_file).expect("while opening index");
+            let mut out = std::io::BufWriter::new(writer(output).expect("while creating output"));
+            scan::scan(&m, fasta_file, *sequence_length, *format, &mut out)
+                .expect("while scanning");
+            std::io::Write::flush(&mut out).expect("while writing track");
+        }
+        Commands::Query {
+            index_file,
+            fasta_file,
+            sequence_length,
+            format,
+            output,
+        } => {
+            let m = database::Database::open(index_file).expect("while opening index");
+            let mut out = std::io::BufWriter::new(writer(output).expect("while creating output"));
+            query::query(&m, fasta_file, *sequence_length, *format, &mut out)
+                .expect("while querying");
+            std::io::Write::flush(&mut out).expect("while writing report");
+        }
+        Commands::Markov {
+            index_file,
+            order,
+            pseudocount,
+            action,
+        } => {
+            let m = database::Database::open(index_file).expect("while opening index");
+            let chain =
+                Markov::from_database(&m, *order, *pseudocount).expect("while deriving chain");
+            markov(&chain, action).expect("while running markov chain");
+        }
+        Commands::Sketch { action } => {
+            sketch(action).expect("while running sketch");
+        }
+        Commands::Presence { action } => {
+            presence(action).expect("while running presence");
         }
         Commands::Visualize {
             index_file,
             sequence_length,
             side_length,
+            prefix,
+            theta_gte,
+            theta_lt,
+            min_seqlen,
+            layout,
+            samples,
+            annotate,
+            motifs,
+            motif_mode,
         } => {
-            print(index_file, *sequence_length, *side_length).expect("while printing");
+            let m = Source::open(index_file).expect("while opening index");
+            let alphabet = m.alphabet();
+            let highlight =
+                motif::Highlight::parse(motifs, *motif_mode).expect("while parsing motifs");
+            if !highlight.motifs.is_empty() {
+                alphabet
+                    .check_dna("motif highlighter")
+                    .expect("while parsing motifs");
+            }
+            if *layout != layout::LayoutKind::Radial {
+                alphabet
+                    .check_dna("square layout")
+                    .expect("while picking layout");
+            }
+            let sector = match prefix {
+                Some(prefix) => Sector::from_prefix(alphabet, prefix, *sequence_length)
+                    .and_then(|sector| match min_seqlen {
+                        Some(min_seqlen) => sector.with_min_seqlen(*min_seqlen),
+                        None => Ok(sector),
+                    })
+                    .expect("while parsing prefix"),
+                None => Sector::from_thetas(
+                    theta_gte.unwrap_or(0.0),
+                    theta_lt.unwrap_or(2.0 * std::f64::consts::PI),
+                    min_seqlen.unwrap_or(1),
+                    *sequence_length,
+                )
+                .and_then(|sector| sector.with_alphabet(alphabet))
+                .expect("while parsing theta range"),
+            };
+            let legend = annotate.then(|| {
+                let mut title = format!(
+                    "{} seqlen={} side={} layout={}",
+                    index_file,
+                    sector.max_seqlen,
+                    side_length,
+                    format!("{:?}", layout).to_lowercase()
+                );
+                if let Some(prefix) = prefix {
+                    title.push_str(&format!(" prefix={}", prefix));
+                } else if theta_gte.is_some() || theta_lt.is_some() {
+                    title.push_str(&format!(
+                        " theta=[{:.3},{:.3})",
+                        sector.gte * 2.0 * std::f64::consts::PI,
+                        sector.lt * 2.0 * std::f64::consts::PI
+                    ));
+                }
+                annotate::Legend {
+                    title,
+                    sector: (*layout == layout::LayoutKind::Radial).then_some(sector),
+                    symbols: alphabet.symbols(),
+                    max_count: 0,
+                    count_label: String::new(),
+                    keys: motif::keys(&highlight.motifs),
+                }
+            });
+            let layout = layout::make_layout(*layout, sector, (*samples).max(1));
+            print(
+                index_file,
+                &m,
+                layout.as_ref(),
+                sector.max_seqlen,
+                *side_length,
+                legend,
+                &highlight,
+            )
+            .expect("while printing");
+        }
+        Commands::Svg {
+            index_file,
+            sequence_length,
+            side_length,
+            max_wedges,
+            output,
+            annotate,
+            motifs,
+            motif_mode,
+        } => {
+            let highlight =
+                motif::Highlight::parse(motifs, *motif_mode).expect("while parsing motifs");
+            print_svg(
+                index_file,
+                *sequence_length,
+                *side_length,
+                *max_wedges,
+                output,
+                *annotate,
+                &highlight,
+            )
+            .expect("while printing svg");
+        }
+        Commands::Diff {
+            index_file_a,
+            index_file_b,
+            sequence_length,
+            side_length,
+            metric,
+            top,
+            output,
+        } => {
+            print_diff(
+                index_file_a,
+                index_file_b,
+                *sequence_length,
+                *side_length,
+                *metric,
+                *top,
+                output,
+            )
+            .expect("while printing diff");
+        }
+        Commands::Animate {
+            index_files,
+            sequence_length,
+            side_length,
+            output,
+            delay_ms,
+        } => {
+            let ms: Vec<database::Database> = index_files
+                .iter()
+                .map(|f| database::Database::open(f).expect("while opening index"))
+                .collect();
+            let frames = if ms.len() == 1 {
+                animate::seqlen_frames(&ms[0], *side_length, *sequence_length)
+            } else {
+                animate::index_frames(&ms, *side_length, *sequence_length)
+            }
+            .expect("while rendering frames");
+            animate::write(output, &frames, *delay_ms).expect("while writing frames");
+        }
+        Commands::Fcgr {
+            index_file,
+            sequence_length,
+            output,
+        } => {
+            let m = database::Database::open(index_file).expect("while opening index");
+            let matrix = fcgr::matrix(&m, *sequence_length).expect("while building matrix");
+            fcgr::write(output, &matrix).expect("while writing matrix");
+        }
+        Commands::Enrichment {
+            index_file,
+            sequence_length,
+            order,
+            side_length,
+            output,
+        } => {
+            print_enrichment(index_file, *sequence_length, *order, *side_length, output)
+                .expect("while computing enrichment");
+        }
+        Commands::Distance {
+            index_files,
+            sequence_length,
+            metric,
+            tree,
+            output,
+        } => {
+            print_distance(index_files, *sequence_length, *metric, *tree, output)
+                .expect("while computing distances");
+        }
+        Commands::Tiles {
+            index_file,
+            sequence_length,
+            output_dir,
+            format,
+            tile_size,
+            max_zoom,
+        } => {
+            let m = database::Database::open(index_file).expect("while opening index");
+            let sector = Sector::full(*sequence_length)
+                .with_alphabet(m.alphabet())
+                .expect("while laying out the star");
+            tiles::export(&m, sector, *format, *tile_size, *max_zoom, output_dir)
+                .expect("while exporting tiles");
+        }
+        Commands::View {
+            index_file,
+            sequence_length,
+            side_length,
+            zoom,
+            left,
+            top,
+            dump,
+        } => {
+            let v = view::View::new(*side_length as u32, *sequence_length as u32)
+                .zoomed(*zoom, *left, *top);
+            match dump {
+                Some(path) => {
+                    let m = database::Database::open(index_file).expect("while opening index");
+                    view::dump(&m, &v, path).expect("while dumping view");
+                }
+                None => view_window(index_file.clone(), v),
+            }
         }
-        _ => panic!("oh no"),
     }
 }
 
-fn print(index_file: &str, seqlen: usize, side_length: usize) -> Result<()> {
-    use crate::database::Database;
-    use crate::traverse::point_chunk_id;
-    use crate::traverse::Point;
-    use crossbeam::channel::unbounded;
-    use std::collections::BTreeMap;
+// Source is what visualize renders: the counts of an index or the estimates of
+// a sketch.
+#[derive(Clone)]
+enum Source {
+    Index(database::Database),
+    Sketch(hustar::sketch::Sketch),
+}
 
-    let cpus = num_cpus::get();
-    let thread_count = cpus;
-    let num_chunks = thread_count * 8;
+impl Source {
+    fn open(path: &str) -> Result<Source> {
+        use hustar::sketch::Sketch;
 
-    info!(
-        "printing {} with thread_count={} num_chunks={}",
-        index_file, thread_count, num_chunks
-    );
+        Ok(match Sketch::open(path) {
+            Ok(s) => Source::Sketch(s),
+            Err(hustar::Error::InvalidSketch(_)) => Source::Index(database::Database::open(path)?),
+            Err(e) => return Err(e.into()),
+        })
+    }
+}
 
-    info!("opening database");
-    let m = Database::open(index_file)?;
-    info!("done opening database");
+impl std::ops::Index<usize> for Source {
+    type Output = u32;
+    fn index(&self, index: usize) -> &u32 {
+        match self {
+            Source::Index(m) => &m[index],
+            Source::Sketch(s) => &s[index],
+        }
+    }
+}
 
-    let width = side_length;
-    let height = side_length;
+impl CountSource for Source {
+    fn alphabet(&self) -> &hustar::Alphabet {
+        match self {
+            Source::Index(m) => m.alphabet(),
+            Source::Sketch(s) => s.alphabet(),
+        }
+    }
 
-    info!("generating pixels");
-    let pixels = make_points(width as u32, height as u32, seqlen as u32);
-    let mut chunk_pixels: BTreeMap<usize, Vec<Point>> = BTreeMap::new();
-    for pixel in pixels {
-        let chunk_id = point_chunk_id(num_chunks, &pixel);
-        chunk_pixels
-            .entry(chunk_id)
-            .or_insert_with(|| Vec::new())
-            .push(pixel);
+    fn check_seqlen(&self, max_seqlen: usize) -> hustar::Result<()> {
+        match self {
+            Source::Index(m) => m.check_seqlen(max_seqlen),
+            Source::Sketch(s) => CountSource::check_seqlen(s, max_seqlen),
+        }
     }
-    for (chunk_id, pixels) in &chunk_pixels {
-        info!("chunk_id={} num_pixels={}", chunk_id, pixels.len());
+
+    fn get(&self, seq: &str) -> hustar::Result<u32> {
+        match self {
+            Source::Index(m) => m.get(seq),
+            Source::Sketch(s) => s.get(seq),
+        }
     }
-    info!("done generating pixels");
-    let (work_tx, work_rx) = unbounded();
-    thread::spawn(move || {
-        for (_, pixels) in chunk_pixels {
-            work_tx.send(pixels).expect("while sending chunk of pixels");
+}
+
+// writer opens output, or stdout when it is not given.
+fn writer(output: &Option<String>) -> Result<Box<dyn std::io::Write>> {
+    Ok(match output {
+        Some(path) => Box::new(std::fs::File::create(path)?),
+        None => Box::new(std::io::stdout().lock()),
+    })
+}
+
+// markov exports, scores or generates with the chain as action asks.
+fn markov(chain: &Markov, action: &MarkovAction) -> Result<()> {
+    use std::io::Write;
+
+    match action {
+        MarkovAction::Export { output } => {
+            let mut out = std::io::BufWriter::new(writer(output)?);
+            writeln!(out, "context\tsymbol\tprobability")?;
+            for (context, symbol, p) in chain.transitions() {
+                writeln!(out, "{}\t{}\t{}", context, symbol, p)?;
+            }
+            out.flush()?;
         }
-    });
-    let (tx, rx) = channel::<(usize, usize, (u64, usize), usize)>();
-    for worker_id in 0..thread_count {
-        let tx = tx.clone();
-        let m = m.clone();
-        let work_rx = work_rx.clone();
-        thread::spawn(move || {
-            let mut pixel_counter = 0;
-            let mut chunk_counter = 0;
-            info!("spawned thread worker_id={}", worker_id);
-            for pixels in work_rx {
-                let mut acc = Accumulator::default();
-                for p in pixels {
-                    let (gte, lt) = p.index_range();
-                    let c = acc.sum_to(&m, gte, lt);
-                    let val = (p.w as usize, p.h as usize, (c, lt - gte), p.seqlen);
-                    tx.send(val).unwrap();
-                    pixel_counter += 1;
-                }
-                chunk_counter += 1;
-                info!(
-                    "worker_id={} finished chunk chunk_counter={}",
-                    worker_id, chunk_counter
-                );
+        MarkovAction::Score { fasta_file, output } => {
+            let mut out = std::io::BufWriter::new(writer(output)?);
+            writeln!(out, "id\tpositions\tlog_likelihood\tper_position")?;
+            for record in bio::io::fasta::Reader::from_file(fasta_file)?.records() {
+                let record = record?;
+                let score = chain.score(record.seq());
+                writeln!(
+                    out,
+                    "{}\t{}\t{}\t{}",
+                    record.id(),
+                    score.positions,
+                    score.log_likelihood,
+                    score.per_position()
+                )?;
             }
-            info!(
-                "thread exiting worker_id={} processed pixel_counter={} chunk_counter={}",
-                worker_id, pixel_counter, chunk_counter
-            );
-        });
+            out.flush()?;
+        }
+        MarkovAction::Generate {
+            length,
+            count,
+            seed,
+            output,
+        } => {
+            let mut out = std::io::BufWriter::new(writer(output)?);
+            for i in 0..*count {
+                let seed = seed.wrapping_add(i as u64);
+                writeln!(out, ">markov_{} order={} seed={}", i, chain.order(), seed)?;
+                writeln!(out, "{}", chain.generate(*length, seed))?;
+            }
+            out.flush()?;
+        }
     }
-    let mut buf: Vec<u64> = vec![0; width * height];
-    let mut maxes: Vec<u64> = vec![0; seqlen + 1];
-    let mut maxesbuf: Vec<u64> = vec![0; width * height];
-    drop(tx);
-    let mut last = Instant::now();
-    let mut counter = 0;
-    let mut count_sequences = 0;
-    while let Ok((x, y, (count, count_unique_sequences), len)) = rx.recv() {
-        buf[y * width + x] = count;
-        if maxes[len] < count {
-            maxes[len] = count;
-        }
-        counter = counter + 1;
-        count_sequences += count_unique_sequences;
-        let now = Instant::now();
-        if now.duration_since(last) > Duration::from_secs_f64(2.3) {
-            last = now;
-            let total = width * height;
-            let percentage = counter as f64 / total as f64 * 100.0;
+    Ok(())
+}
+
+// sketch builds, queries or exports a sketch as action asks.
+fn sketch(action: &SketchAction) -> Result<()> {
+    use hustar::sketch::Sketch;
+    use std::io::Write;
+
+    match action {
+        SketchAction::Build {
+            fasta_file,
+            sketch_file,
+            sequence_length,
+            alphabet,
+            width,
+            depth,
+        } => {
+            let header = hustar::Header::new(alphabet.parse()?, *sequence_length);
+            hustar::sketch::build(fasta_file, sketch_file, header, *width, *depth)?;
+        }
+        SketchAction::Get {
+            sketch_file,
+            sequences,
+        } => {
+            let s = Sketch::open(sketch_file)?;
             info!(
-                "processed {} pixels of {} ({:.2}%)",
-                counter, total, percentage
+                "estimates exceed counts by at most {} with probability {}",
+                s.epsilon() * s.total() as f64,
+                1.0 - s.delta()
             );
+            let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+            for seq in sequences {
+                writeln!(out, "{}\t{}", seq, s.get(seq)?)?;
+            }
+            out.flush()?;
+        }
+        SketchAction::Export {
+            sketch_file,
+            index_file,
+            sequence_length,
+        } => {
+            Sketch::open(sketch_file)?.to_database(index_file, *sequence_length)?;
+        }
+    }
+    Ok(())
+}
+
+// presence builds, derives or queries a presence index as action asks.
+fn presence(action: &PresenceAction) -> Result<()> {
+    use hustar::presence::{self, Presence};
+    use std::io::Write;
+
+    match action {
+        PresenceAction::Build {
+            fasta_file,
+            presence_file,
+            sequence_length,
+            alphabet,
+        } => {
+            let header = hustar::Header::new(alphabet.parse()?, *sequence_length);
+            presence::build(fasta_file, presence_file, header)?;
+        }
+        PresenceAction::Derive {
+            index_file,
+            presence_file,
+        } => {
+            let m = database::Database::open(index_file)?;
+            presence::from_database(&m, presence_file)?;
+        }
+        PresenceAction::Get {
+            presence_file,
+            sequences,
+        } => {
+            let p = Presence::open(presence_file)?;
+            let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+            for seq in sequences {
+                writeln!(out, "{}\t{}", seq, p.contains(seq)? as u8)?;
+            }
+            out.flush()?;
         }
+        PresenceAction::Rings { presence_file } => {
+            let p = Presence::open(presence_file)?;
+            let alphabet = p.alphabet();
+            let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+            writeln!(out, "seqlen\tobserved\tpossible")?;
+            for seqlen in 1..=p.header().seqlen.unwrap_or(0) {
+                let (gte, lt) = (alphabet.base_index(seqlen), alphabet.base_index(seqlen + 1));
+                writeln!(out, "{}\t{}\t{}", seqlen, p.count(gte, lt), lt - gte)?;
+            }
+            out.flush()?;
+        }
+    }
+    Ok(())
+}
+
+// locate writes the occurrences of every sequence to stdout as bed.
+fn locate(index_file: &str, sequences: &[String]) -> Result<()> {
+    use hustar::positions::{path_for, Positions};
+    use std::io::Write;
+
+    let m = database::Database::open(index_file)?;
+    let p = Positions::open(path_for(index_file))?;
+    if p.len() != m.len() {
+        anyhow::bail!(
+            "{} does not belong to {}",
+            path_for(index_file).display(),
+            index_file
+        );
     }
-    info!("visited {} sequences", count_sequences);
-    info!("creating maxes buffer");
-    for y in 0..height {
-        for x in 0..width {
-            maxesbuf[y * height + x] = maxes[seqlen];
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    for seq in sequences {
+        let index = m.index_of(seq)?;
+        let len = m.header().window_len(m.header().key(seq).len());
+        for (record, start) in p.locate(index) {
+            writeln!(out, "{}\t{}\t{}\t{}", record, start, start + len, seq)?;
         }
     }
-    info!("maxes {:?}", maxes);
+    Ok(())
+}
+
+fn print<C: CountSource>(
+    index_file: &str,
+    m: &C,
+    layout: &dyn layout::Layout,
+    seqlen: usize,
+    side_length: usize,
+    legend: Option<annotate::Legend>,
+    highlight: &motif::Highlight,
+) -> Result<()> {
+    info!("printing {}", index_file);
+
+    let width = side_length;
+    let height = side_length;
+
+    let (pixels, counts) = render::render(m, layout, width, height, seqlen);
     info!("creating image buffer");
-    let mut img = ImageBuffer::from_fn(width as u32, height as u32, |x, y| {
-        let x = x as usize;
-        let y = y as usize;
-        let c = buf[y * width + x];
-        let t = maxesbuf[y * height + x];
-        let p = c as f64 / t as f64;
-        let p = p.sqrt().sqrt();
-        let l = (p * 255.0) as u8;
-        image::Luma([l])
-    });
+    // supersampled layouts hand out several pixels per image pixel, which only
+    // blend by area once every ring is scaled to its own max count per
+    // sequence. Plain renders keep scaling by the outer ring's pixel count.
+    let (img, max_count, count_label) = if pixels.len() > width * height {
+        let ring_maxes = render::ring_maxes(m, seqlen)?;
+        (
+            render::density_image(&pixels, &counts, &ring_maxes),
+            ring_maxes[seqlen] as u64,
+            format!(
+                "count per sequence, seqlen={} (each ring scaled to its max)",
+                seqlen
+            ),
+        )
+    } else {
+        (
+            counts.to_image(),
+            *counts.maxes.last().unwrap_or(&0),
+            format!("pixel count, seqlen={}", seqlen),
+        )
+    };
+    let img = if highlight.motifs.is_empty() {
+        image::DynamicImage::ImageLuma8(img).into_rgb8()
+    } else {
+        info!("highlighting {} motifs", highlight.motifs.len());
+        let highlights =
+            motif::highlights(&pixels, &highlight.motifs, highlight.mode, width, height);
+        motif::highlight(&img, &highlights)
+    };
+    let img = match legend {
+        Some(mut legend) => {
+            legend.max_count = max_count;
+            legend.count_label = count_label;
+            annotate::annotate(&img, &legend)
+        }
+        None => img,
+    };
     img.save_with_format("out.png", image::ImageFormat::Png)
         .expect("while writing image");
     Ok(())
 }
 
-use crossbeam::channel::Sender;
-
-fn read_into<'a>(
-    fasta_file: &'a str,
-    record_id: &str,
+fn print_svg(
+    index_file: &str,
     seqlen: usize,
-    into: &Sender<String>,
+    side_length: usize,
+    max_wedges: usize,
+    output: &str,
+    annotate: bool,
+    highlight: &motif::Highlight,
 ) -> Result<()> {
+    use crate::database::Database;
+    use std::fs::File;
+    use std::io::BufWriter;
+
     info!(
-        "reading file={} with record_id={} with seqlen={}",
-        fasta_file, record_id, seqlen
+        "printing {} as svg with max_wedges={} into {}",
+        index_file, max_wedges, output
     );
-    let r = Reader::from_file(fasta_file)?;
-    let mut records = r.records();
-    while let Some(Ok(record)) = records.next() {
-        if record.id() != record_id {
-            continue;
-        }
-        for seq in record.seq().windows(seqlen).filter(filter_n) {
-            let seq = String::from_utf8(seq.to_vec())?;
-            into.send(seq)?;
-        }
+    let m = Database::open(index_file)?;
+    m.alphabet().check_dna("svg renderer")?;
+    let mut wedges = svg::make_wedges(seqlen, max_wedges);
+    info!("counting {} wedges", wedges.len());
+    svg::count_wedges(&m, &mut wedges);
+    if !highlight.motifs.is_empty() {
+        info!("highlighting {} motifs", highlight.motifs.len());
+        svg::highlight_wedges(&mut wedges, &highlight.motifs, highlight.mode);
     }
-    info!(
-        "done reading file={} with record_id={} with seqlen={}",
-        fasta_file, record_id, seqlen
-    );
+    let legend = annotate.then(|| annotate::Legend {
+        title: format!("{} seqlen={} max_wedges={}", index_file, seqlen, max_wedges),
+        sector: Some(Sector::full(seqlen)),
+        symbols: m.alphabet().symbols(),
+        max_count: svg::ring_max(&wedges, seqlen),
+        count_label: format!(
+            "wedge count, seqlen={} (each ring scaled to its max)",
+            seqlen
+        ),
+        keys: motif::keys(&highlight.motifs),
+    });
+    let mut out = BufWriter::new(File::create(output)?);
+    svg::write_svg(&mut out, &wedges, seqlen, side_length, legend.as_ref())?;
     Ok(())
 }
 
-fn record_ids(fasta_file: &str) -> Result<Vec<String>> {
-    let mut result = Vec::new();
-    let r = Reader::from_file(fasta_file)?;
-    let mut records = r.records();
-    while let Some(Ok(record)) = records.next() {
-        result.push(record.id().to_string());
-    }
-    Ok(result)
+// print_diff writes the differential star to <output>.png, the per ring
+// statistics to <output>.rings.tsv and the most differing sequences of the
+// outermost ring to <output>.top.tsv.
+fn print_diff(
+    index_file_a: &str,
+    index_file_b: &str,
+    seqlen: usize,
+    side_length: usize,
+    metric: diff::Metric,
+    top: usize,
+    output: &str,
+) -> Result<()> {
+    use crate::database::Database;
+    use std::fs::File;
+    use std::io::BufWriter;
+
+    info!(
+        "comparing {} with {} using {:?}",
+        index_file_a, index_file_b, metric
+    );
+    let a = Database::open(index_file_a)?;
+    let b = Database::open(index_file_b)?;
+    let totals_a = diff::ring_totals(&a, seqlen)?;
+    let totals_b = diff::ring_totals(&b, seqlen)?;
+    info!("totals_a={:?} totals_b={:?}", totals_a, totals_b);
+
+    let width = side_length;
+    let height = side_length;
+    let pixels = make_points(width as u32, height as u32, Sector::full(seqlen));
+    let counts_a = render::count_points(&a, pixels.clone(), width, height, seqlen);
+    let counts_b = render::count_points(&b, pixels.clone(), width, height, seqlen);
+    let values = diff::compare_points(metric, &pixels, &counts_a, &totals_a, &counts_b, &totals_b);
+    diff::diverging(&values, width, height).save(format!("{}.png", output))?;
+
+    info!("computing ring statistics");
+    let stats = diff::ring_stats(&a, &totals_a, &b, &totals_b);
+    let mut out = BufWriter::new(File::create(format!("{}.rings.tsv", output))?);
+    diff::write_ring_stats(&mut out, &stats)?;
+
+    info!("finding top {} differences", top);
+    let differences = diff::top_differences(metric, &a, &totals_a, &b, &totals_b, seqlen, top);
+    let mut out = BufWriter::new(File::create(format!("{}.top.tsv", output))?);
+    diff::write_differences(&mut out, &differences)?;
+    Ok(())
 }
 
-fn create<P: Into<PathBuf>>(fasta_file: &str, outpath: P, seqlen: usize) -> Result<()> {
-    let fasta_file = fasta_file.to_owned();
-    let cpus = num_cpus::get();
+fn print_enrichment(
+    index_file: &str,
+    seqlen: usize,
+    order: usize,
+    side_length: Option<usize>,
+    output: &str,
+) -> Result<()> {
+    use std::fs::File;
+    use std::io::{BufWriter, Write};
 
+    let m = database::Database::open(index_file)?;
+    let model = enrichment::Model::new(&m, order, seqlen)?;
     info!(
-        "building index from fasta_file={} seqlen={} with cpus={}",
-        fasta_file, seqlen, cpus
+        "writing enrichment of seqlen={} with order={}",
+        seqlen, order
     );
+    let mut out = BufWriter::new(File::create(format!("{}.tsv", output))?);
+    model.write_ring(&mut out, seqlen)?;
+    out.flush()?;
 
-    let mut db = database::DatabaseMut::create(outpath, seqlen)?;
+    if let Some(side_length) = side_length {
+        info!("rendering enrichment");
+        let sector = Sector::full(seqlen).with_alphabet(m.alphabet())?;
+        let points = make_points(side_length as u32, side_length as u32, sector);
+        let values = model.log_ratios(&points, side_length, side_length);
+        diff::diverging(&values, side_length, side_length).save(format!("{}.png", output))?;
+    }
+    Ok(())
+}
 
-    info!("reading record ids");
-    let record_ids = record_ids(&fasta_file)?;
-    info!("done reading record ids");
-    let (tx, rx) = unbounded();
-    let (tx_sequences, rx_sequences) = bounded(0);
-    for seqlen in 1..=seqlen {
-        for record_id in &record_ids {
-            let val = (record_id.clone(), seqlen);
-            info!("sending {:?}", val);
-            tx.send(val)?;
-        }
+fn print_distance(
+    index_files: &[String],
+    seqlen: usize,
+    metric: distance::Metric,
+    tree: bool,
+    output: &str,
+) -> Result<()> {
+    use std::fs::File;
+    use std::io::{BufWriter, Write};
+    use std::path::Path;
+
+    let ms = index_files
+        .iter()
+        .map(database::Database::open)
+        .collect::<hustar::Result<Vec<_>>>()?;
+    // indexes are named after their files without the extension.
+    let names: Vec<String> = index_files
+        .iter()
+        .map(|f| {
+            Path::new(f)
+                .file_stem()
+                .map_or(f.clone(), |s| s.to_string_lossy().into_owned())
+        })
+        .collect();
+    let matrix = distance::matrix(metric, &ms, seqlen)?;
+    let mut out = BufWriter::new(File::create(format!("{}.tsv", output))?);
+    distance::write_matrix(&mut out, &names, &matrix)?;
+    out.flush()?;
+    if tree {
+        let newick = distance::neighbor_joining(&names, &matrix);
+        std::fs::write(format!("{}.nwk", output), format!("{}\n", newick))?;
     }
-    drop(tx);
-    for worker_id in 0..cpus - 1 {
-        let rx = rx.clone();
-        let fasta_file = fasta_file.clone();
-        let tx_sequences = tx_sequences.clone();
-        thread::spawn(move || {
-            info!("worker_id={} starting", worker_id);
-            for (record_id, seqlen) in rx {
-                read_into(&fasta_file, &record_id, seqlen, &tx_sequences)
-                    .expect("while reading into");
+    Ok(())
+}
+
+// view_window opens a window onto the view. The mouse wheel zooms around the cursor,
+// dragging with the left button or the arrow keys pan, hovering logs the
+// sequences and count under the cursor, and 's' dumps the current view next to
+// the index.
+fn view_window(index_file: String, mut view: view::View) -> ! {
+    use crate::database::Database;
+    use crate::render::ring_maxes;
+    use crate::view::describe;
+    use show_image::event::{
+        ElementState, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+    };
+    use show_image::{BoxImage, ImageInfo, WindowOptions};
+
+    show_image::run_context(move || -> Result<()> {
+        let m = Database::open(&index_file)?;
+        m.alphabet().check_dna("viewer")?;
+        m.check_seqlen(view.max_length as usize)?;
+        let ring_maxes = ring_maxes(&m, view.max_length as usize)?;
+        let side = view.side_length;
+        let window = show_image::create_window(
+            "hustar",
+            WindowOptions {
+                size: Some([side, side]),
+                resizable: false,
+                default_controls: false,
+                ..WindowOptions::default()
+            },
+        )?;
+        let events = window.event_channel()?;
+        let (mut counts, mut image) = view.render(&m, &ring_maxes);
+        let mut hovered: Option<(u32, u32)> = None;
+        let mut window_size = (side as f32, side as f32);
+        loop {
+            let shown = image.clone().into_raw();
+            let shown = BoxImage::new(ImageInfo::mono8(side, side), shown.into());
+            window.set_image("star", shown)?;
+            let before = view;
+            while view == before {
+                let event = match events.recv() {
+                    Ok(event) => event,
+                    Err(_) => return Ok(()),
+                };
+                let at = |x: f32, y: f32| {
+                    let w = (x / window_size.0 * side as f32) as u32;
+                    let h = (y / window_size.1 * side as f32) as u32;
+                    (w.min(side - 1), h.min(side - 1))
+                };
+                match event {
+                    WindowEvent::Resized(e) => {
+                        window_size = (e.size.x as f32, e.size.y as f32);
+                    }
+                    WindowEvent::CloseRequested(_) => return Ok(()),
+                    WindowEvent::MouseMove(e) => {
+                        let (w, h) = at(e.position.x, e.position.y);
+                        if e.buttons.is_pressed(MouseButton::Left) {
+                            let (pw, ph) = at(e.prev_position.x, e.prev_position.y);
+                            view.pan(pw as i64 - w as i64, ph as i64 - h as i64);
+                        } else if hovered != Some((w, h)) {
+                            hovered = Some((w, h));
+                            info!("{}", describe(&view, &counts, w, h));
+                        }
+                    }
+                    WindowEvent::MouseWheel(e) => {
+                        let (w, h) = e
+                            .position
+                            .map(|p| at(p.x, p.y))
+                            .unwrap_or((side / 2, side / 2));
+                        let dy = match e.delta {
+                            MouseScrollDelta::LineDelta(_, dy) => dy,
+                            MouseScrollDelta::PixelDelta(p) => p.y as f32,
+                        };
+                        if dy > 0.0 {
+                            view.zoom_in(w, h);
+                        } else if dy < 0.0 {
+                            view.zoom_out(w, h);
+                        }
+                    }
+                    WindowEvent::KeyboardInput(e) if e.input.state == ElementState::Pressed => {
+                        let step = (side / 4) as i64;
+                        match e.input.key_code {
+                            Some(VirtualKeyCode::Left) => view.pan(-step, 0),
+                            Some(VirtualKeyCode::Right) => view.pan(step, 0),
+                            Some(VirtualKeyCode::Up) => view.pan(0, -step),
+                            Some(VirtualKeyCode::Down) => view.pan(0, step),
+                            Some(VirtualKeyCode::Escape) => return Ok(()),
+                            Some(VirtualKeyCode::S) => {
+                                let path = format!(
+                                    "{}.zoom={}.left={}.top={}.png",
+                                    index_file, view.zoom, view.left, view.top
+                                );
+                                image.save(&path)?;
+                                info!("wrote view to {}", path);
+                            }
+                            _ => {}
+                        }
+                    }
+                    _ => {}
+                }
             }
-            info!("worker_id={} exiting", worker_id);
-        });
-    }
-    drop(tx_sequences);
-    let mut counter: u64 = 0;
-    let mut last = Instant::now();
-    for seq in rx_sequences {
-        db[&seq[..]] += 1;
-        counter += 1;
-        if last.elapsed() > Duration::from_secs_f64(1.0) {
-            info!("inserted count={} sequences", counter);
-            last = Instant::now();
+            info!("rendering {:?}", view);
+            (counts, image) = view.render(&m, &ring_maxes);
         }
-    }
-    Ok(())
+    })
 }
@@ -0,0 +1,227 @@
+//! Markov chains derived from an index. An index of seqlen k holds the counts
+//! of every sequence of length 1..=k, which are the transitions of chains of
+//! every order up to k-1.
+
+use crate::alphabet::Alphabet;
+use crate::database::Database;
+use crate::error::{Error, Result};
+
+/// Markov is an order-k chain over the alphabet of an index. The first k
+/// symbols of a sequence are scored and generated by the chains of lower
+/// order, so that every position of a sequence has a probability.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Markov {
+    alphabet: Alphabet,
+    order: usize,
+    // probs is laid out as the index: the probability at the index of a
+    // sequence is that of its last symbol following the symbols before it.
+    probs: Vec<f64>,
+}
+
+/// Score is the log likelihood of a sequence.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Score {
+    /// log_likelihood is the natural log of the probability of the sequence.
+    pub log_likelihood: f64,
+    /// positions counts the letters that were scored, which are those in the
+    /// alphabet.
+    pub positions: usize,
+}
+
+impl Score {
+    pub fn per_position(&self) -> f64 {
+        if self.positions == 0 {
+            return 0.0;
+        }
+        self.log_likelihood / self.positions as f64
+    }
+}
+
+// splitmix64 steps the state of the generator and returns its next output.
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl Markov {
+    /// from_database derives a chain of order from m, adding pseudocount to
+    /// the count of every transition. Contexts that were never followed by a
+    /// symbol and get no pseudocount are followed by every symbol alike.
+    pub fn from_database(m: &Database, order: usize, pseudocount: f64) -> Result<Markov> {
+        let header = m.header();
+        if header.seed.is_some() || header.sampling.is_some() {
+            return Err(Error::InvalidModel(
+                "spaced seed and sampled indexes do not hold the counts of every window"
+                    .to_string(),
+            ));
+        }
+        if pseudocount.is_nan() || pseudocount < 0.0 {
+            return Err(Error::InvalidModel(format!(
+                "pseudocount={} must not be negative",
+                pseudocount
+            )));
+        }
+        m.check_seqlen(order + 1)?;
+        let alphabet = m.alphabet().clone();
+        let radix = alphabet.radix();
+        let mut probs = vec![0.0; alphabet.base_index(order + 2)];
+        for len in 1..=order + 1 {
+            let base = alphabet.base_index(len);
+            for context in 0..alphabet.ring_len(len - 1) {
+                let first = base + context * radix;
+                let total: f64 = (first..first + radix).map(|i| m[i] as f64).sum();
+                let total = total + pseudocount * radix as f64;
+                for i in first..first + radix {
+                    probs[i] = if total > 0.0 {
+                        (m[i] as f64 + pseudocount) / total
+                    } else {
+                        1.0 / radix as f64
+                    };
+                }
+            }
+        }
+        Ok(Markov {
+            alphabet,
+            order,
+            probs,
+        })
+    }
+
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    pub fn alphabet(&self) -> &Alphabet {
+        &self.alphabet
+    }
+
+    /// transitions iterates the context, the symbol following it and its
+    /// probability, for every context of length 0..=order in index order.
+    pub fn transitions(&self) -> impl Iterator<Item = (String, char, f64)> + '_ {
+        self.probs.iter().enumerate().map(|(i, p)| {
+            let mut context = self.alphabet.index_to_seq(i);
+            let symbol = context.pop().expect("sequences are never empty");
+            (context, symbol, *p)
+        })
+    }
+
+    /// probability returns the probability of the last of codes, symbol codes
+    /// of the alphabet, following the ones before it. codes must be
+    /// 1..=order+1 long.
+    pub fn probability(&self, codes: &[usize]) -> f64 {
+        let (last, context) = codes.split_last().expect("codes are never empty");
+        let addr = context
+            .iter()
+            .fold(0, |acc, c| acc * self.alphabet.radix() + c);
+        self.probs[self.index(addr, context.len(), *last)]
+    }
+
+    // index returns where the probability of the symbol code following
+    // context, the address of len symbols, is.
+    fn index(&self, context: usize, len: usize, code: usize) -> usize {
+        self.alphabet.base_index(len + 1) + context * self.alphabet.radix() + code
+    }
+
+    // push appends code to context and drops its first symbol once it is
+    // longer than the order.
+    fn push(&self, context: &mut usize, len: &mut usize, code: usize) {
+        *context = *context * self.alphabet.radix() + code;
+        if *len < self.order {
+            *len += 1;
+        } else {
+            *context %= self.alphabet.ring_len(self.order);
+        }
+    }
+
+    /// score returns the log likelihood of seq. Letters outside of the
+    /// alphabet are skipped, and the sequence after them is scored as if it
+    /// started there.
+    pub fn score(&self, seq: &[u8]) -> Score {
+        let mut score = Score {
+            log_likelihood: 0.0,
+            positions: 0,
+        };
+        let (mut context, mut len) = (0, 0);
+        for l in seq {
+            match self.alphabet.code(*l) {
+                Some(code) => {
+                    score.log_likelihood += self.probs[self.index(context, len, code)].ln();
+                    score.positions += 1;
+                    self.push(&mut context, &mut len, code);
+                }
+                None => (context, len) = (0, 0),
+            }
+        }
+        score
+    }
+
+    /// generate returns a random sequence of length len drawn from the chain.
+    /// The same seed always generates the same sequence.
+    pub fn generate(&self, len: usize, seed: u64) -> String {
+        let symbols: Vec<char> = self.alphabet.symbols().chars().collect();
+        let mut state = seed;
+        let (mut context, mut context_len) = (0, 0);
+        let mut seq = String::with_capacity(len);
+        for _ in 0..len {
+            // the top 53 bits make a uniform f64 in [0, 1).
+            let u = (splitmix64(&mut state) >> 11) as f64 / (1_u64 << 53) as f64;
+            let first = self.index(context, context_len, 0);
+            let mut code = 0;
+            let mut sum = self.probs[first];
+            while sum <= u && code + 1 < symbols.len() {
+                code += 1;
+                sum += self.probs[first + code];
+            }
+            seq.push(symbols[code]);
+            self.push(&mut context, &mut context_len, code);
+        }
+        seq
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::temp_build;
+    use crate::header::Header;
+
+    #[test]
+    fn test_markov() -> Result<()> {
+        let m = temp_build(">one\nACGTAC\n", Header::new(Alphabet::dna(), 2));
+        // a and c make up 2 of 6 letters, and ac, cg, gt and ta the 2-mers.
+        let chain = Markov::from_database(&m, 1, 0.0)?;
+        let score = chain.score(b"ACG");
+        assert_eq!(3, score.positions);
+        assert!((score.log_likelihood - (1.0_f64 / 3.0).ln()).abs() < 1e-12);
+        // n restarts the chain, and ca never occurs.
+        assert_eq!(3, chain.score(b"ANCA").positions);
+        assert_eq!(f64::NEG_INFINITY, chain.score(b"CA").log_likelihood);
+
+        assert_eq!(1.0, chain.probability(&[1, 2]));
+        assert_eq!(2.0 / 6.0, chain.probability(&[0]));
+
+        let transitions: Vec<(String, char, f64)> = chain.transitions().collect();
+        assert_eq!(4 + 16, transitions.len());
+        assert_eq!(("a".to_string(), 'c', 1.0), transitions[4 + 1]);
+
+        // every context of the order 1 chain has a single successor.
+        let seq = chain.generate(9, 7);
+        assert_eq!(9, seq.len());
+        assert!("acgtacgtacgtacgt".contains(&seq));
+        assert_eq!(seq, chain.generate(9, 7));
+
+        let smoothed = Markov::from_database(&m, 1, 1.0)?;
+        assert_eq!(
+            ("a".to_string(), 'c', 0.5),
+            smoothed.transitions().nth(5).unwrap()
+        );
+        assert!(smoothed.score(b"CA").log_likelihood.is_finite());
+
+        assert!(Markov::from_database(&m, 2, 1.0).is_err());
+        assert!(Markov::from_database(&m, 1, -1.0).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,288 @@
+// motif highlights the sequences matching known motifs on a rendered star.
+// Motifs are IUPAC patterns such as "tataww" or "cg", or regular expressions
+// prefixed with "re:". Every pixel whose index range holds a matching sequence
+// is colored by the first motif that matches it.
+
+use crate::alphabet::base_index;
+use crate::error::{Error, Result};
+use crate::kmer::RADIX;
+use crate::layout::Pixel;
+use clap::ArgEnum;
+use image::{GrayImage, ImageBuffer, Rgb, RgbImage};
+use regex::Regex;
+
+const PALETTE: [[u8; 3]; 6] = [
+    [255, 64, 64],
+    [64, 200, 64],
+    [64, 128, 255],
+    [255, 200, 0],
+    [200, 64, 255],
+    [0, 220, 220],
+];
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq)]
+pub enum MatchMode {
+    // the motif occurs anywhere in the sequence.
+    Contains,
+    // the sequence begins with the motif.
+    StartsWith,
+}
+
+#[derive(Debug, Clone)]
+enum Pattern {
+    // Iupac holds the nucleotides allowed at every position as a bitmask of
+    // a=1, c=2, g=4 and t=8.
+    Iupac(Vec<u8>),
+    Regex(Regex),
+}
+
+#[derive(Debug, Clone)]
+pub struct Motif {
+    pub name: String,
+    pattern: Pattern,
+}
+
+fn nucleotide_mask(n: u8) -> u8 {
+    match n {
+        b'a' => 1,
+        b'c' => 2,
+        b'g' => 4,
+        b't' => 8,
+        _ => 0,
+    }
+}
+
+fn iupac_mask(l: char) -> Option<u8> {
+    let mask = match l.to_ascii_lowercase() {
+        'a' => 0b0001,
+        'c' => 0b0010,
+        'g' => 0b0100,
+        't' | 'u' => 0b1000,
+        'r' => 0b0101,
+        'y' => 0b1010,
+        's' => 0b0110,
+        'w' => 0b1001,
+        'k' => 0b1100,
+        'm' => 0b0011,
+        'b' => 0b1110,
+        'd' => 0b1101,
+        'h' => 0b1011,
+        'v' => 0b0111,
+        'n' => 0b1111,
+        _ => return None,
+    };
+    Some(mask)
+}
+
+impl Motif {
+    pub fn parse(motif: &str) -> Result<Motif> {
+        if let Some(re) = motif.strip_prefix("re:") {
+            let regex = Regex::new(&format!("(?i){}", re))
+                .map_err(|e| Error::InvalidMotif(format!("{:?}: {}", motif, e)))?;
+            return Ok(Motif {
+                name: motif.to_string(),
+                pattern: Pattern::Regex(regex),
+            });
+        }
+        if motif.is_empty() {
+            return Err(Error::InvalidMotif("empty motif".to_string()));
+        }
+        let mut masks = Vec::with_capacity(motif.len());
+        for l in motif.chars() {
+            match iupac_mask(l) {
+                Some(mask) => masks.push(mask),
+                None => {
+                    return Err(Error::InvalidMotif(format!(
+                        "invalid IUPAC letter {} in motif {:?}",
+                        l, motif
+                    )))
+                }
+            }
+        }
+        Ok(Motif {
+            name: motif.to_string(),
+            pattern: Pattern::Iupac(masks),
+        })
+    }
+
+    // matches tells whether the lowercase acgt sequence holds the motif.
+    pub fn matches(&self, seq: &[u8], mode: MatchMode) -> bool {
+        match &self.pattern {
+            Pattern::Iupac(masks) => {
+                let at = |start: usize| {
+                    masks
+                        .iter()
+                        .zip(&seq[start..])
+                        .all(|(mask, n)| mask & nucleotide_mask(*n) != 0)
+                };
+                if masks.len() > seq.len() {
+                    return false;
+                }
+                match mode {
+                    MatchMode::StartsWith => at(0),
+                    MatchMode::Contains => (0..=seq.len() - masks.len()).any(at),
+                }
+            }
+            Pattern::Regex(regex) => {
+                let seq = std::str::from_utf8(seq).unwrap_or_default();
+                match mode {
+                    // the leftmost match starts at 0 whenever any match does.
+                    MatchMode::StartsWith => regex.find(seq).is_some_and(|m| m.start() == 0),
+                    MatchMode::Contains => regex.is_match(seq),
+                }
+            }
+        }
+    }
+}
+
+// Highlight is the set of motifs to highlight and how they must match.
+pub struct Highlight {
+    pub motifs: Vec<Motif>,
+    pub mode: MatchMode,
+}
+
+impl Highlight {
+    pub fn parse(motifs: &[String], mode: MatchMode) -> Result<Highlight> {
+        let motifs = motifs
+            .iter()
+            .map(|m| Motif::parse(m))
+            .collect::<Result<_>>()?;
+        Ok(Highlight { motifs, mode })
+    }
+}
+
+// keys names every motif with its highlight color for a legend.
+pub fn keys(motifs: &[Motif]) -> Vec<(String, [u8; 3])> {
+    motifs
+        .iter()
+        .enumerate()
+        .map(|(i, m)| (m.name.clone(), color(i)))
+        .collect()
+}
+
+// addr_to_seq decodes the 2-bit address of a sequence of length seqlen.
+fn addr_to_seq(addr: usize, seqlen: usize, seq: &mut Vec<u8>) {
+    seq.clear();
+    for i in (0..seqlen).rev() {
+        seq.push(b"acgt"[(addr >> (2 * i)) & 0b11]);
+    }
+}
+
+// first_match returns the position of the first motif matching any sequence in
+// the [gte, lt) index range of a ring.
+pub fn first_match(
+    motifs: &[Motif],
+    mode: MatchMode,
+    seqlen: usize,
+    gte: usize,
+    lt: usize,
+) -> Option<usize> {
+    let base = base_index(RADIX, seqlen);
+    let mut seq = Vec::with_capacity(seqlen);
+    let mut found: Option<usize> = None;
+    for index in gte..lt {
+        addr_to_seq(index - base, seqlen, &mut seq);
+        // motifs past the one already found cannot change the result.
+        let searched = found.unwrap_or(motifs.len());
+        if let Some(i) = motifs[..searched]
+            .iter()
+            .position(|m| m.matches(&seq, mode))
+        {
+            found = Some(i);
+            if i == 0 {
+                break;
+            }
+        }
+    }
+    found
+}
+
+// highlights returns the first motif matching every image pixel in row major
+// order. Pixels sharing w and h take the lowest motif of any of them.
+pub fn highlights(
+    pixels: &[Pixel],
+    motifs: &[Motif],
+    mode: MatchMode,
+    width: usize,
+    height: usize,
+) -> Vec<Option<usize>> {
+    let mut out: Vec<Option<usize>> = vec![None; width * height];
+    for p in pixels {
+        let i = p.h as usize * width + p.w as usize;
+        if let Some(found) = first_match(motifs, mode, p.seqlen, p.gte, p.lt) {
+            out[i] = Some(out[i].map_or(found, |prev| prev.min(found)));
+        }
+    }
+    out
+}
+
+pub fn color(motif: usize) -> [u8; 3] {
+    PALETTE[motif % PALETTE.len()]
+}
+
+// highlight colors the matched pixels of the grayscale star. The motif color is
+// dimmed by the pixel's brightness, but never below a third, so that matches
+// without counts still show up.
+pub fn highlight(img: &GrayImage, highlights: &[Option<usize>]) -> RgbImage {
+    let width = img.width() as usize;
+    ImageBuffer::from_fn(img.width(), img.height(), |x, y| {
+        let l = img.get_pixel(x, y).0[0];
+        match highlights[y as usize * width + x as usize] {
+            Some(motif) => {
+                let f = 0.35 + 0.65 * l as f64 / 255.0;
+                let c = color(motif);
+                Rgb(c.map(|c| (c as f64 * f) as u8))
+            }
+            None => Rgb([l, l, l]),
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_matches() -> Result<()> {
+        let tata = Motif::parse("TATAWA")?;
+        assert!(tata.matches(b"ctataaag", MatchMode::Contains));
+        assert!(tata.matches(b"tatata", MatchMode::StartsWith));
+        assert!(!tata.matches(b"ctataaag", MatchMode::StartsWith));
+        assert!(!tata.matches(b"tata", MatchMode::Contains));
+        assert!(Motif::parse("cgx").is_err());
+
+        let site = Motif::parse("re:g(aa|tt)c")?;
+        assert!(site.matches(b"agaac", MatchMode::Contains));
+        assert!(!site.matches(b"agaac", MatchMode::StartsWith));
+        assert!(site.matches(b"gttca", MatchMode::StartsWith));
+        assert!(Motif::parse("re:(").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_highlights() -> Result<()> {
+        let motifs = vec![Motif::parse("cg")?, Motif::parse("gn")?];
+        let base = base_index(RADIX, 2);
+        let pixel = |w, gte, lt| Pixel {
+            w,
+            h: 0,
+            seqlen: 2,
+            gte: base + gte,
+            lt: base + lt,
+        };
+        // ac, ca..cc, cg..ct and gg..gt.
+        let pixels = vec![
+            pixel(0, 1, 2),
+            pixel(1, 4, 6),
+            pixel(2, 6, 8),
+            pixel(3, 10, 12),
+        ];
+        let found = highlights(&pixels, &motifs, MatchMode::StartsWith, 4, 1);
+        assert_eq!(vec![None, None, Some(0), Some(1)], found);
+
+        let img = highlight(&GrayImage::new(4, 1), &found);
+        assert_eq!(&Rgb([89, 22, 22]), img.get_pixel(2, 0));
+        assert_eq!(&Rgb([0, 0, 0]), img.get_pixel(0, 0));
+        Ok(())
+    }
+}
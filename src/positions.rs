@@ -0,0 +1,224 @@
+//! The positional sidecar of an index, which lists where every sequence
+//! occurs. It is a compressed sparse row layout over the slots of the index:
+//! offsets[i]..offsets[i + 1] are the occurrences of the sequence at index i,
+//! each a record number and the start of the window in that record, sorted by
+//! record and start. The record ids are kept in a text header like the one of
+//! the index:
+//!
+//! ```text
+//! hustar-positions 1
+//! slots=21
+//! occurrences=9
+//! record=chr1
+//! record=chr2
+//! ```
+//!
+//! followed by the u64 offsets and the [record, start] u32 pairs.
+
+use crate::build::{reader, windows};
+use crate::database::Database;
+use crate::error::{io_error, Error, Result};
+use memmap2::{Mmap, MmapMut};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+const MAGIC: &[u8] = b"hustar-positions 1\n";
+const ALIGN: usize = 64;
+
+/// Positions is an open positional sidecar.
+pub struct Positions {
+    mmap: Mmap,
+    records: Vec<String>,
+    slots: usize,
+    // offset is where the offsets start, after the header.
+    offset: usize,
+}
+
+/// path_for returns where the sidecar of index_file lives.
+pub fn path_for<P: AsRef<Path>>(index_file: P) -> PathBuf {
+    let mut path = index_file.as_ref().as_os_str().to_owned();
+    path.push(".pos");
+    PathBuf::from(path)
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidPositions(reason.to_string())
+}
+
+/// build writes the sidecar of an index built from fasta_file next to it. It
+/// reads the fasta file once more and must be given the same one as the index.
+pub fn build<P: AsRef<Path>>(fasta_file: &str, index_file: P) -> Result<()> {
+    use std::fs::OpenOptions;
+    use std::io::Write;
+
+    let m = Database::open(index_file.as_ref())?;
+    let header = m.header();
+    let seqlen = header
+        .seqlen
+        .ok_or_else(|| invalid("the index has no header to read its seqlen from"))?;
+    let records = crate::build::record_ids(fasta_file)?;
+    if records.len() > u32::MAX as usize {
+        return Err(invalid("too many records"));
+    }
+    let slots = m.len();
+    let occurrences: u64 = (0..slots).map(|i| m[i] as u64).sum();
+    info!(
+        "writing positions of slots={} occurrences={} records={}",
+        slots,
+        occurrences,
+        records.len()
+    );
+
+    let mut text = MAGIC.to_vec();
+    text.extend_from_slice(format!("slots={}\noccurrences={}\n", slots, occurrences).as_bytes());
+    for record in &records {
+        text.extend_from_slice(format!("record={}\n", record).as_bytes());
+    }
+    text.push(0);
+    text.resize(text.len().div_ceil(ALIGN) * ALIGN, 0);
+
+    let path = path_for(index_file.as_ref());
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&path)
+        .map_err(io_error(&path))?;
+    file.write_all(&text).map_err(io_error(&path))?;
+    let size = text.len() as u64 + 8 * (slots as u64 + 1) + 8 * occurrences;
+    file.set_len(size).map_err(io_error(&path))?;
+    let mut mmap = unsafe { MmapMut::map_mut(&file).map_err(io_error(&path))? };
+    let (offsets, entries) = mmap[text.len()..].split_at_mut(8 * (slots + 1));
+    let (_, offsets, _) = unsafe { offsets.align_to_mut::<u64>() };
+    let (_, entries, _) = unsafe { entries.align_to_mut::<[u32; 2]>() };
+
+    // the counts are the lengths of the rows.
+    for i in 0..slots {
+        offsets[i + 1] = offsets[i] + m[i] as u64;
+    }
+    let mut cursors = offsets[..slots].to_vec();
+    let mut fasta = reader(fasta_file)?.records();
+    let mut record_id = 0;
+    while let Some(Ok(record)) = fasta.next() {
+        for len in 1..=seqlen {
+            for (start, index) in windows(header, record.seq(), len) {
+                if cursors[index] >= offsets[index + 1] || start > u32::MAX as usize {
+                    return Err(invalid("the fasta file does not match the index"));
+                }
+                entries[cursors[index] as usize] = [record_id, start as u32];
+                cursors[index] += 1;
+            }
+        }
+        record_id += 1;
+    }
+    if cursors.iter().zip(&offsets[1..]).any(|(c, o)| c != o) {
+        return Err(invalid("the fasta file does not match the index"));
+    }
+    mmap.flush().map_err(io_error(&path))?;
+    Ok(())
+}
+
+impl Positions {
+    pub fn open<P: Into<PathBuf>>(path: P) -> Result<Positions> {
+        let path = path.into();
+        let file = std::fs::File::open(&path).map_err(io_error(&path))?;
+        let mmap = unsafe { Mmap::map(&file).map_err(io_error(&path))? };
+        let text = mmap
+            .strip_prefix(MAGIC)
+            .ok_or_else(|| invalid("not a positions file"))?;
+        let end = text
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or_else(|| invalid("missing the end of the header"))?;
+        let offset = (MAGIC.len() + end + 1).div_ceil(ALIGN) * ALIGN;
+        let text = std::str::from_utf8(&text[..end]).map_err(|e| invalid(&e.to_string()))?;
+        let (mut slots, mut occurrences, mut records) = (None, None, Vec::new());
+        for line in text.lines() {
+            match line.split_once('=') {
+                Some(("slots", n)) => slots = n.parse::<usize>().ok(),
+                Some(("occurrences", n)) => occurrences = n.parse::<usize>().ok(),
+                Some(("record", id)) => records.push(id.to_string()),
+                _ => return Err(invalid(&format!("unexpected line {:?}", line))),
+            }
+        }
+        let (slots, occurrences) = slots
+            .zip(occurrences)
+            .ok_or_else(|| invalid("missing slots or occurrences"))?;
+        if mmap.len() < offset + 8 * (slots + 1) + 8 * occurrences {
+            return Err(invalid("the file is truncated"));
+        }
+        Ok(Positions {
+            mmap,
+            records,
+            slots,
+            offset,
+        })
+    }
+
+    /// len returns the number of slots, which matches the index it belongs to.
+    pub fn len(&self) -> usize {
+        self.slots
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots == 0
+    }
+
+    pub fn records(&self) -> &[String] {
+        &self.records
+    }
+
+    /// get returns the [record, start] pairs of the sequence at index.
+    pub fn get(&self, index: usize) -> &[[u32; 2]] {
+        let (offsets, entries) = self.mmap[self.offset..].split_at(8 * (self.slots + 1));
+        let (_, offsets, _) = unsafe { offsets.align_to::<u64>() };
+        let (_, entries, _) = unsafe { entries.align_to::<[u32; 2]>() };
+        &entries[offsets[index] as usize..offsets[index + 1] as usize]
+    }
+
+    /// locate returns the record id and start of every occurrence of the
+    /// sequence at index.
+    pub fn locate(&self, index: usize) -> impl Iterator<Item = (&str, usize)> + '_ {
+        self.get(index)
+            .iter()
+            .map(|[record, start]| (self.records[*record as usize].as_str(), *start as usize))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::alphabet::Alphabet;
+    use crate::database::temp_fasta;
+    use crate::header::Header;
+
+    #[test]
+    fn test_positions() -> Result<()> {
+        let fasta = temp_fasta(">one\nACGNAC\n>two\nTAC\n");
+        let fasta = fasta.path().to_str().unwrap();
+        // the index lives in a directory of its own, which also holds the
+        // sidecar written next to it.
+        let dir = tempfile::tempdir().unwrap();
+        let index = dir.path().join("positions.bin");
+        crate::build::build(fasta, &index, Header::new(Alphabet::dna(), 2))?;
+        build(fasta, &index)?;
+
+        let m = Database::open(&index)?;
+        let p = Positions::open(path_for(&index))?;
+        assert_eq!(m.len(), p.len());
+        assert_eq!(["one", "two"], p.records());
+        let ac: Vec<(&str, usize)> = p.locate(m.index_of("ac")?).collect();
+        assert_eq!(vec![("one", 0), ("one", 4), ("two", 1)], ac);
+        let a: Vec<(&str, usize)> = p.locate(m.index_of("a")?).collect();
+        assert_eq!(vec![("one", 0), ("one", 4), ("two", 1)], a);
+        assert_eq!(0, p.locate(m.index_of("gg")?).count());
+
+        let other = temp_fasta(">one\nACGT\n");
+        assert!(matches!(
+            build(other.path().to_str().unwrap(), &index),
+            Err(Error::InvalidPositions(_))
+        ));
+        Ok(())
+    }
+}
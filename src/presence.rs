@@ -0,0 +1,290 @@
+//! Presence indexes, which keep one bit per sequence telling whether it occurs
+//! at all. They are laid out as the index with the same header, so an index of
+//! seqlen k takes an eighth of a byte per sequence of length 1..=k instead of
+//! four bytes.
+//!
+//! The header carries presence=bits in its metadata and is followed by the
+//! bits in u64 words, least significant bit first, and by the number of ones
+//! before every block of 512 bits, which answer rank and select queries without
+//! counting the whole index.
+
+use crate::build::{reader, windows};
+use crate::database::{checked_index, Database};
+use crate::error::{io_error, Error, Result};
+use crate::header::Header;
+use bitvec::prelude::*;
+use core::ops::Index;
+use memmap2::{Mmap, MmapMut};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+const WORD_BITS: usize = u64::BITS as usize;
+// BLOCK_BITS is the number of bits between two stored ranks.
+const BLOCK_BITS: usize = 8 * WORD_BITS;
+
+/// Presence is a read only presence index.
+pub struct Presence {
+    mmap: Mmap,
+    header: Header,
+    offset: usize,
+    len: usize,
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidPresence(reason.to_string())
+}
+
+// layout returns the number of words and of stored ranks of len bits.
+fn layout(len: usize) -> (usize, usize) {
+    let words = len.div_ceil(WORD_BITS);
+    (words, words.div_ceil(BLOCK_BITS / WORD_BITS) + 1)
+}
+
+// create makes a presence index of len zero bits at path, calls fill to set
+// them and then stores the ranks.
+fn create<P, F>(path: P, header: Header, len: usize, fill: F) -> Result<()>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&mut BitSlice<u64, Lsb0>) -> Result<()>,
+{
+    use std::fs::OpenOptions;
+    use std::io::Write;
+
+    let mut header = header;
+    header
+        .metadata
+        .insert("presence".to_string(), "bits".to_string());
+    let encoded = header.encode();
+    let (words, blocks) = layout(len);
+    let path = path.as_ref();
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+        .map_err(io_error(path))?;
+    file.write_all(&encoded).map_err(io_error(path))?;
+    file.set_len((encoded.len() + 8 * (words + blocks)) as u64)
+        .map_err(io_error(path))?;
+    let mut mmap = unsafe { MmapMut::map_mut(&file).map_err(io_error(path))? };
+    let (_, buf, _) = unsafe { mmap[encoded.len()..].align_to_mut::<u64>() };
+    let (words, ranks) = buf.split_at_mut(words);
+    let bits = words.view_bits_mut::<Lsb0>();
+    fill(&mut bits[..len])?;
+    let mut ones = 0;
+    for (block, rank) in ranks.iter_mut().enumerate() {
+        *rank = ones;
+        let start = (block * BLOCK_BITS).min(len);
+        let end = (start + BLOCK_BITS).min(len);
+        ones += bits[start..end].count_ones() as u64;
+    }
+    mmap.flush().map_err(io_error(path))?;
+    Ok(())
+}
+
+/// from_database writes the presence index of m to path.
+pub fn from_database<P: AsRef<Path>>(m: &Database, path: P) -> Result<()> {
+    info!("deriving presence of {} sequences", m.len());
+    create(path, m.header().clone(), m.len(), |bits| {
+        for i in 0..m.len() {
+            bits.set(i, m[i] > 0);
+        }
+        Ok(())
+    })
+}
+
+/// build marks every subsequence of length 1..=seqlen of every record of the
+/// fasta file in a new presence index at outpath, laid out as header
+/// describes, without counting them first.
+pub fn build<P: AsRef<Path>>(fasta_file: &str, outpath: P, header: Header) -> Result<()> {
+    let seqlen = header
+        .seqlen
+        .ok_or_else(|| Error::InvalidHeader("missing seqlen".to_string()))?;
+    if let Some(seed) = &header.seed {
+        seed.check_len(seqlen)?;
+    }
+    header.alphabet.check_index_len(seqlen)?;
+    let len = header.alphabet.base_index(seqlen + 1);
+    info!(
+        "building presence from fasta_file={} seqlen={} alphabet={}",
+        fasta_file, seqlen, header.alphabet
+    );
+    create(outpath, header.clone(), len, |bits| {
+        for record in reader(fasta_file)?.records() {
+            let record = record.map_err(|e| Error::Fasta(format!("{}: {}", fasta_file, e)))?;
+            for len in 1..=seqlen {
+                for (_, index) in windows(&header, record.seq(), len) {
+                    bits.set(index, true);
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+impl Presence {
+    pub fn open<P: Into<PathBuf>>(path: P) -> Result<Presence> {
+        let path = path.into();
+        let file = std::fs::File::open(&path).map_err(io_error(&path))?;
+        let mmap = unsafe { Mmap::map(&file).map_err(io_error(&path))? };
+        let (header, offset) = Header::decode(&mmap)?;
+        if header.metadata.get("presence").map(|p| p.as_str()) != Some("bits") {
+            return Err(invalid("not a presence index"));
+        }
+        let seqlen = header.seqlen.ok_or_else(|| invalid("missing seqlen"))?;
+        let len = header.alphabet.base_index(seqlen + 1);
+        let (words, blocks) = layout(len);
+        if mmap.len() < offset + 8 * (words + blocks) {
+            return Err(invalid("the file is truncated"));
+        }
+        Ok(Presence {
+            mmap,
+            header,
+            offset,
+            len,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn alphabet(&self) -> &crate::alphabet::Alphabet {
+        &self.header.alphabet
+    }
+
+    /// len returns the number of sequences the index holds a bit for.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn words(&self) -> (&[u64], &[u64]) {
+        let (_, buf, _) = unsafe { self.mmap[self.offset..].align_to::<u64>() };
+        let (words, blocks) = layout(self.len);
+        let (words, ranks) = buf.split_at(words);
+        (words, &ranks[..blocks])
+    }
+
+    /// bits returns the bit of every sequence, in index order.
+    pub fn bits(&self) -> &BitSlice<u64, Lsb0> {
+        &self.words().0.view_bits::<Lsb0>()[..self.len]
+    }
+
+    /// index_of returns the position of the bit of seq, which is a key or, for
+    /// spaced seed indexes, a full window of the seed.
+    pub fn index_of(&self, seq: &str) -> Result<usize> {
+        let key = self.header.key(seq);
+        checked_index(self.alphabet(), &key, self.len)
+    }
+
+    /// contains returns whether seq occurs.
+    pub fn contains(&self, seq: &str) -> Result<bool> {
+        Ok(self.bits()[self.index_of(seq)?])
+    }
+
+    /// rank returns the number of sequences that occur among the indexes
+    /// below index.
+    pub fn rank(&self, index: usize) -> usize {
+        let index = index.min(self.len);
+        let block = index / BLOCK_BITS;
+        let (_, ranks) = self.words();
+        ranks[block] as usize + self.bits()[block * BLOCK_BITS..index].count_ones()
+    }
+
+    /// count returns the number of sequences that occur in [gte, lt).
+    pub fn count(&self, gte: usize, lt: usize) -> usize {
+        self.rank(lt).saturating_sub(self.rank(gte))
+    }
+
+    /// select returns the index of the sequence that occurs with rank
+    /// sequences before it, or None when fewer occur.
+    pub fn select(&self, rank: usize) -> Option<usize> {
+        let (_, ranks) = self.words();
+        let rank = rank as u64;
+        if rank >= ranks[ranks.len() - 1] {
+            return None;
+        }
+        // the block holding it is the last one starting at or below rank.
+        let block = ranks.partition_point(|r| *r <= rank) - 1;
+        let start = block * BLOCK_BITS;
+        let end = (start + BLOCK_BITS).min(self.len);
+        self.bits()[start..end]
+            .iter_ones()
+            .nth((rank - ranks[block]) as usize)
+            .map(|i| start + i)
+    }
+}
+
+impl Index<usize> for Presence {
+    type Output = bool;
+    fn index(&self, index: usize) -> &Self::Output {
+        if self.bits()[index] {
+            &true
+        } else {
+            &false
+        }
+    }
+}
+
+impl Index<&str> for Presence {
+    type Output = bool;
+    fn index(&self, seq: &str) -> &Self::Output {
+        &self[self.index_of(seq).unwrap_or_else(|e| panic!("{}", e))]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::alphabet::Alphabet;
+    use crate::database::temp_fasta;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_presence() -> Result<()> {
+        let fasta = temp_fasta(">one\nACGNAC\n>two\nTTTTACG\n");
+        let fasta = fasta.path().to_str().unwrap();
+        let (index, derived, built) = (
+            NamedTempFile::new().unwrap(),
+            NamedTempFile::new().unwrap(),
+            NamedTempFile::new().unwrap(),
+        );
+        let (index, derived, built) = (index.path(), derived.path(), built.path());
+        crate::build::build(fasta, index, Header::new(Alphabet::dna(), 5))?;
+        let m = Database::open(index)?;
+        from_database(&m, derived)?;
+        build(fasta, built, Header::new(Alphabet::dna(), 5))?;
+
+        let p = Presence::open(derived)?;
+        assert_eq!(m.len(), p.len());
+        assert_eq!(p.bits(), Presence::open(built)?.bits());
+        for i in 0..m.len() {
+            assert_eq!(m[i] > 0, p[i]);
+        }
+        assert!(p["acg"]);
+        assert!(!p.contains("gg")?);
+        assert!(p.contains("acgtac").is_err());
+
+        let ones: Vec<usize> = (0..m.len()).filter(|i| m[*i] > 0).collect();
+        assert_eq!(ones.len(), p.rank(p.len()));
+        for (rank, index) in ones.iter().enumerate() {
+            assert_eq!(rank, p.rank(*index));
+            assert_eq!(Some(*index), p.select(rank));
+        }
+        assert_eq!(None, p.select(ones.len()));
+        // a, c, g and t all occur.
+        assert_eq!(4, p.count(0, 4));
+
+        assert!(Database::open(derived).is_err());
+        assert!(matches!(
+            Presence::open(index),
+            Err(Error::InvalidPresence(_))
+        ));
+        Ok(())
+    }
+}
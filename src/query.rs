@@ -0,0 +1,234 @@
+// query reports how much of the windows of query sequences, such as reads,
+// genes or contigs, an index holds. Records are read in batches and every batch
+// is split across threads, and reports are written in the order of the records
+// as tsv or as json lines.
+
+use crate::build::reader;
+use crate::database::Database;
+use crate::error::{Error, Result};
+use crate::scan::windows;
+use bio::io::fasta::Record;
+use clap::ArgEnum;
+use serde::Serialize;
+use std::io::Write;
+use std::thread;
+use tracing::info;
+
+// BATCH is the number of records read before they are looked up.
+const BATCH: usize = 4096;
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    Tsv,
+    // Json writes one object per record and line.
+    Json,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Report {
+    pub id: String,
+    // windows counts the windows of the query holding only letters of the
+    // alphabet, and distinct the different ones among them.
+    pub windows: usize,
+    pub distinct: usize,
+    // found counts the windows that occur in the index and novel the rest.
+    pub found: usize,
+    pub novel: usize,
+    pub novel_fraction: f64,
+    // mean_count and max_count are over the counts in the index of the found
+    // windows.
+    pub mean_count: f64,
+    pub max_count: u32,
+    // containment is the fraction of distinct windows found in the index, and
+    // jaccard the number of distinct windows found over the number of distinct
+    // windows of the query and the index together.
+    pub containment: f64,
+    pub jaccard: f64,
+}
+
+const TSV_HEADER: &str = "id\twindows\tdistinct\tfound\tnovel\tnovel_fraction\tmean_count\tmax_count\tcontainment\tjaccard";
+
+// ratio is 0 rather than NaN for queries without windows.
+fn ratio(a: usize, b: usize) -> f64 {
+    if b == 0 {
+        0.0
+    } else {
+        a as f64 / b as f64
+    }
+}
+
+// observed counts the distinct sequences of length len the index holds, which
+// is the size of its side of the jaccard score.
+pub fn observed(m: &Database, len: usize) -> Result<usize> {
+    m.check_seqlen(len)?;
+    let base = m.alphabet().base_index(len);
+    let ring = base..base + m.alphabet().ring_len(len);
+    Ok(ring.filter(|i| m[*i] > 0).count())
+}
+
+// report looks up every window of length len of seq. observed is the result of
+// observed for the same index and len.
+pub fn report(m: &Database, id: &str, seq: &[u8], len: usize, observed: usize) -> Result<Report> {
+    let mut indexes: Vec<usize> = windows(m, seq, len)?.map(|(_, index)| index).collect();
+    let counts: Vec<u32> = indexes.iter().map(|i| m[*i]).collect();
+    let found: Vec<u32> = counts.iter().copied().filter(|c| *c > 0).collect();
+    indexes.sort_unstable();
+    indexes.dedup();
+    let shared = indexes.iter().filter(|i| m[**i] > 0).count();
+    Ok(Report {
+        id: id.to_string(),
+        windows: counts.len(),
+        distinct: indexes.len(),
+        found: found.len(),
+        novel: counts.len() - found.len(),
+        novel_fraction: ratio(counts.len() - found.len(), counts.len()),
+        mean_count: ratio(found.iter().map(|c| *c as usize).sum(), found.len()),
+        max_count: found.iter().copied().max().unwrap_or(0),
+        containment: ratio(shared, indexes.len()),
+        jaccard: ratio(shared, indexes.len() + observed - shared),
+    })
+}
+
+// reports splits records across a thread per cpu and returns their reports in
+// order.
+pub fn reports(
+    m: &Database,
+    records: &[Record],
+    len: usize,
+    observed: usize,
+) -> Result<Vec<Report>> {
+    let chunk_len = records.len().div_ceil(num_cpus::get()).max(1);
+    thread::scope(|s| {
+        let workers: Vec<_> = records
+            .chunks(chunk_len)
+            .map(|chunk| {
+                s.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|r| report(m, r.id(), r.seq(), len, observed))
+                        .collect::<Result<Vec<Report>>>()
+                })
+            })
+            .collect();
+        let mut reports = Vec::with_capacity(records.len());
+        for worker in workers {
+            reports.extend(worker.join().expect("while joining query worker")?);
+        }
+        Ok(reports)
+    })
+}
+
+fn write_report<W: Write>(out: &mut W, report: &Report, format: ReportFormat) -> Result<()> {
+    match format {
+        ReportFormat::Tsv => writeln!(
+            out,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            report.id,
+            report.windows,
+            report.distinct,
+            report.found,
+            report.novel,
+            report.novel_fraction,
+            report.mean_count,
+            report.max_count,
+            report.containment,
+            report.jaccard
+        )
+        .map_err(Error::Output)?,
+        ReportFormat::Json => {
+            serde_json::to_writer(&mut *out, report).map_err(|e| Error::Output(e.into()))?;
+            writeln!(out).map_err(Error::Output)?;
+        }
+    }
+    Ok(())
+}
+
+// query writes the report of every record of the fasta file to out.
+pub fn query<W: Write>(
+    m: &Database,
+    fasta_file: &str,
+    len: usize,
+    format: ReportFormat,
+    out: &mut W,
+) -> Result<()> {
+    let observed = observed(m, len)?;
+    info!(
+        "index holds observed={} sequences of seqlen={}",
+        observed, len
+    );
+    if format == ReportFormat::Tsv {
+        writeln!(out, "{}", TSV_HEADER).map_err(Error::Output)?;
+    }
+    let mut records = reader(fasta_file)?.records();
+    let mut batch = Vec::with_capacity(BATCH);
+    loop {
+        batch.clear();
+        for record in records.by_ref().take(BATCH) {
+            batch.push(record.map_err(|e| Error::Fasta(format!("{}: {}", fasta_file, e)))?);
+        }
+        if batch.is_empty() {
+            return Ok(());
+        }
+        info!("querying batch of {} records", batch.len());
+        for report in reports(m, &batch, len, observed)? {
+            write_report(out, &report, format)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::alphabet::Alphabet;
+    use crate::database::{temp_build, temp_fasta};
+    use crate::header::Header;
+
+    #[test]
+    fn test_query() -> Result<()> {
+        let m = temp_build(">genome\nACGTAC\n", Header::new(Alphabet::dna(), 2));
+        // the genome holds ac twice, cg, gt and ta.
+        assert_eq!(4, observed(&m, 2)?);
+
+        // ac, ca, ac and cc, of which ac is found twice.
+        let report = super::report(&m, "read", b"ACACNCC", 2, 4)?;
+        assert_eq!(
+            (4, 3, 2, 2),
+            (report.windows, report.distinct, report.found, report.novel)
+        );
+        assert_eq!(
+            (0.5, 2.0, 2),
+            (report.novel_fraction, report.mean_count, report.max_count)
+        );
+        assert_eq!((1.0 / 3.0, 1.0 / 6.0), (report.containment, report.jaccard));
+
+        let queries = temp_fasta(">one\nCGTA\n>two\nN\n");
+        let queries = queries.path();
+        let mut out = Vec::new();
+        query(
+            &m,
+            queries.to_str().unwrap(),
+            2,
+            ReportFormat::Tsv,
+            &mut out,
+        )?;
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(TSV_HEADER, lines[0]);
+        assert_eq!("one\t3\t3\t3\t0\t0\t1\t1\t1\t0.75", lines[1]);
+        assert_eq!("two\t0\t0\t0\t0\t0\t0\t0\t0\t0", lines[2]);
+
+        let mut out = Vec::new();
+        query(
+            &m,
+            queries.to_str().unwrap(),
+            2,
+            ReportFormat::Json,
+            &mut out,
+        )?;
+        let out = String::from_utf8(out).unwrap();
+        let first = out.lines().next().unwrap();
+        assert!(first.starts_with("{\"id\":\"one\",\"windows\":3,"));
+        assert!(first.ends_with("\"jaccard\":0.75}"));
+        Ok(())
+    }
+}
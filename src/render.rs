@@ -0,0 +1,269 @@
+// render turns a set of pixel points into per pixel counts by querying the
+// database from a pool of worker threads, and turns those counts into images.
+
+use crate::accumulator::Accumulator;
+use crate::alphabet::{base_index, ring_len};
+use crate::database::CountSource;
+use crate::error::Result;
+use crate::layout::{Layout, Pixel};
+use crate::traverse::Point;
+use crossbeam::channel::unbounded;
+use image::{GrayImage, ImageBuffer};
+use std::collections::BTreeMap;
+use std::sync::mpsc::channel;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+pub struct Counts {
+    pub width: usize,
+    pub height: usize,
+    // buf holds the count of every pixel in row major order. Pixels that were
+    // supersampled hold the sum of their samples.
+    pub buf: Vec<u64>,
+    // maxes holds the largest pixel count seen for every seqlen, where a pixel
+    // belongs to the outermost seqlen of its samples.
+    pub maxes: Vec<u64>,
+    // samples holds the count of every pixel handed to count_pixels, in the
+    // order they were handed in.
+    pub samples: Vec<u64>,
+}
+
+impl Counts {
+    pub fn get(&self, x: usize, y: usize) -> u64 {
+        self.buf[y * self.width + x]
+    }
+
+    // to_image normalizes every pixel by the largest count of the outermost
+    // ring.
+    pub fn to_image(&self) -> GrayImage {
+        let t = *self.maxes.last().unwrap_or(&0);
+        ImageBuffer::from_fn(self.width as u32, self.height as u32, |x, y| {
+            let c = self.get(x as usize, y as usize);
+            let p = c as f64 / t as f64;
+            let p = p.sqrt().sqrt();
+            let l = (p * 255.0) as u8;
+            image::Luma([l])
+        })
+    }
+}
+
+// ring_maxes returns the largest count of a single sequence in every ring,
+// indexed by seqlen.
+pub fn ring_maxes<C: CountSource>(m: &C, max_seqlen: usize) -> Result<Vec<u32>> {
+    m.check_seqlen(max_seqlen)?;
+    let alphabet = m.alphabet();
+    let mut maxes = vec![0; max_seqlen + 1];
+    for (seqlen, max) in maxes.iter_mut().enumerate().skip(1) {
+        for index in alphabet.base_index(seqlen)..alphabet.base_index(seqlen + 1) {
+            *max = (*max).max(m[index]);
+        }
+    }
+    Ok(maxes)
+}
+
+// density_image colors every pixel by the average count of the sequences under
+// it relative to the largest count in its ring. Unlike the raw sums this does
+// not depend on how many sequences a pixel covers, so images of different sizes,
+// zoom levels or max seqlens share one scale. Supersampled pixels average the
+// densities of their samples, which blends rings and wedges by area. pixels must
+// be in the order they were counted in.
+pub fn density_image(pixels: &[Pixel], counts: &Counts, ring_maxes: &[u32]) -> GrayImage {
+    let mut sums = vec![0.0; counts.width * counts.height];
+    let mut samples = vec![0_u32; counts.width * counts.height];
+    for (p, count) in pixels.iter().zip(&counts.samples) {
+        let density = *count as f64 / (p.lt - p.gte) as f64;
+        let max = ring_maxes[p.seqlen];
+        let i = p.h as usize * counts.width + p.w as usize;
+        sums[i] += if max == 0 { 0.0 } else { density / max as f64 };
+        samples[i] += 1;
+    }
+    ImageBuffer::from_fn(counts.width as u32, counts.height as u32, |x, y| {
+        let i = y as usize * counts.width + x as usize;
+        let p = if samples[i] == 0 {
+            0.0
+        } else {
+            sums[i] / samples[i] as f64
+        };
+        let l = (p.sqrt().sqrt() * 255.0) as u8;
+        image::Luma([l])
+    })
+}
+
+// render lays out a width x height image and counts every pixel of it.
+pub fn render<C: CountSource>(
+    m: &C,
+    layout: &dyn Layout,
+    width: usize,
+    height: usize,
+    seqlen: usize,
+) -> (Vec<Pixel>, Counts) {
+    let pixels = layout.pixels(width as u32, height as u32);
+    let counts = count_pixels(m, pixels.clone(), width, height, seqlen);
+    (pixels, counts)
+}
+
+// pixel_chunk_id assigns a pixel to one of num_chunks chunks by how far into
+// its ring the pixel's index range starts.
+fn pixel_chunk_id(num_chunks: usize, radix: usize, pixel: &Pixel) -> usize {
+    let offset = (pixel.gte - base_index(radix, pixel.seqlen)) as f64;
+    let percentage = (offset / ring_len(radix, pixel.seqlen) as f64).clamp(0.0, 1.0);
+    (percentage * num_chunks as f64) as usize % num_chunks
+}
+
+pub fn count_points<C: CountSource>(
+    m: &C,
+    points: Vec<Point>,
+    width: usize,
+    height: usize,
+    seqlen: usize,
+) -> Counts {
+    let pixels = points.iter().map(Pixel::from).collect();
+    count_pixels(m, pixels, width, height, seqlen)
+}
+
+// count_pixels sums the database over the index range of every pixel. Pixels
+// are split into chunks of neighbouring ranges so that each worker's
+// accumulator mostly walks forward. Several pixels may share the same w and h,
+// in which case they are samples of one image pixel and their counts add up.
+pub fn count_pixels<C: CountSource>(
+    m: &C,
+    pixels: Vec<Pixel>,
+    width: usize,
+    height: usize,
+    seqlen: usize,
+) -> Counts {
+    let cpus = num_cpus::get();
+    let thread_count = cpus;
+    let num_chunks = thread_count * 8;
+
+    info!(
+        "counting {} pixels with thread_count={} num_chunks={}",
+        pixels.len(),
+        thread_count,
+        num_chunks
+    );
+
+    let num_samples = pixels.len();
+    let mut chunk_pixels: BTreeMap<usize, Vec<(usize, Pixel)>> = BTreeMap::new();
+    for (i, pixel) in pixels.into_iter().enumerate() {
+        let chunk_id = pixel_chunk_id(num_chunks, m.alphabet().radix(), &pixel);
+        chunk_pixels.entry(chunk_id).or_default().push((i, pixel));
+    }
+    for (chunk_id, pixels) in &chunk_pixels {
+        info!("chunk_id={} num_pixels={}", chunk_id, pixels.len());
+    }
+    let (work_tx, work_rx) = unbounded();
+    thread::spawn(move || {
+        for (_, pixels) in chunk_pixels {
+            work_tx.send(pixels).expect("while sending chunk of pixels");
+        }
+    });
+    let (tx, rx) = channel::<(usize, usize, usize, (u64, usize), usize)>();
+    for worker_id in 0..thread_count {
+        let tx = tx.clone();
+        let m = m.clone();
+        let work_rx = work_rx.clone();
+        thread::spawn(move || {
+            let mut pixel_counter = 0;
+            let mut chunk_counter = 0;
+            info!("spawned thread worker_id={}", worker_id);
+            for pixels in work_rx {
+                let mut acc = Accumulator::default();
+                for (i, p) in pixels {
+                    let (gte, lt) = (p.gte, p.lt);
+                    let c = acc.sum_to(&m, gte, lt);
+                    let val = (i, p.w as usize, p.h as usize, (c, lt - gte), p.seqlen);
+                    tx.send(val).unwrap();
+                    pixel_counter += 1;
+                }
+                chunk_counter += 1;
+                info!(
+                    "worker_id={} finished chunk chunk_counter={}",
+                    worker_id, chunk_counter
+                );
+            }
+            info!(
+                "thread exiting worker_id={} processed pixel_counter={} chunk_counter={}",
+                worker_id, pixel_counter, chunk_counter
+            );
+        });
+    }
+    let mut buf: Vec<u64> = vec![0; width * height];
+    let mut seqlens: Vec<usize> = vec![0; width * height];
+    let mut samples: Vec<u64> = vec![0; num_samples];
+    drop(tx);
+    let mut last = Instant::now();
+    let mut counter = 0;
+    let mut count_sequences = 0;
+    while let Ok((i, x, y, (count, count_unique_sequences), len)) = rx.recv() {
+        buf[y * width + x] += count;
+        seqlens[y * width + x] = seqlens[y * width + x].max(len);
+        samples[i] = count;
+        counter += 1;
+        count_sequences += count_unique_sequences;
+        let now = Instant::now();
+        if now.duration_since(last) > Duration::from_secs_f64(2.3) {
+            last = now;
+            let total = num_samples;
+            let percentage = counter as f64 / total as f64 * 100.0;
+            info!(
+                "processed {} pixels of {} ({:.2}%)",
+                counter, total, percentage
+            );
+        }
+    }
+    info!("visited {} sequences", count_sequences);
+    let mut maxes: Vec<u64> = vec![0; seqlen + 1];
+    for (count, len) in buf.iter().zip(&seqlens) {
+        if maxes[*len] < *count {
+            maxes[*len] = *count;
+        }
+    }
+    info!("maxes {:?}", maxes);
+    Counts {
+        width,
+        height,
+        buf,
+        maxes,
+        samples,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::temp_database;
+
+    #[test]
+    fn test_count_samples() -> Result<()> {
+        let m = temp_database(2, &[("a", 8), ("c", 2), ("aa", 4)]);
+        let sample = |w, seqlen, gte, lt| Pixel {
+            w,
+            h: 0,
+            seqlen,
+            gte,
+            lt,
+        };
+        let pixels = vec![
+            sample(0, 1, 0, 1),
+            sample(0, 2, 4, 5),
+            sample(1, 1, 1, 2),
+            sample(1, 1, 2, 3),
+        ];
+        let counts = count_pixels(&m, pixels.clone(), 2, 1, 2);
+        assert_eq!(vec![12, 2], counts.buf);
+        assert_eq!(vec![8, 4, 2, 0], counts.samples);
+        assert_eq!(vec![0, 2, 12], counts.maxes);
+
+        // both samples of the first pixel hold the max of their ring, the
+        // samples of the second pixel hold a quarter and none of it.
+        let img = density_image(&pixels, &counts, &ring_maxes(&m, 2)?);
+        assert_eq!(255, img.get_pixel(0, 0).0[0]);
+        assert_eq!(
+            (0.125_f64.sqrt().sqrt() * 255.0) as u8,
+            img.get_pixel(1, 0).0[0]
+        );
+        Ok(())
+    }
+}
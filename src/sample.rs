@@ -0,0 +1,283 @@
+//! Sampling schemes that count only some windows of every record: (w,k)
+//! minimizers and open or closed syncmers. Ring l of a sampled index samples
+//! the windows of length k=l.
+
+use crate::alphabet::Alphabet;
+use crate::error::{Error, Result};
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+/// Scheme picks which windows are sampled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheme {
+    /// Minimizer keeps the smallest of every w consecutive windows.
+    Minimizer { w: usize },
+    /// OpenSyncmer keeps windows whose smallest s long substring is their
+    /// first one.
+    OpenSyncmer { s: usize },
+    /// ClosedSyncmer keeps windows whose smallest s long substring is their
+    /// first or their last one.
+    ClosedSyncmer { s: usize },
+}
+
+/// Order ranks windows and substrings to find the smallest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    /// Lexicographic ranks by the symbols of the alphabet.
+    Lexicographic,
+    /// Hash ranks by a fixed pseudo random permutation, which avoids the bias
+    /// of lexicographic minimizers towards runs of the first symbol.
+    Hash,
+}
+
+/// Sampling is a scheme with the order it ranks by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sampling {
+    pub scheme: Scheme,
+    pub order: Order,
+}
+
+// mix is the finalizer of murmur3, used as the hash order.
+pub(crate) fn mix(mut x: u64) -> u64 {
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    x ^ (x >> 33)
+}
+
+// window_minima returns the position of the leftmost smallest value of every
+// run of w consecutive values.
+fn window_minima(values: &[u64], w: usize) -> Vec<usize> {
+    let mut minima = Vec::with_capacity((values.len() + 1).saturating_sub(w));
+    // candidates holds positions of increasing values, front the smallest.
+    let mut candidates: VecDeque<usize> = VecDeque::new();
+    for (i, v) in values.iter().enumerate() {
+        while candidates.back().is_some_and(|b| values[*b] > *v) {
+            candidates.pop_back();
+        }
+        candidates.push_back(i);
+        if i + 1 < w {
+            continue;
+        }
+        let start = i + 1 - w;
+        while candidates.front().is_some_and(|f| *f < start) {
+            candidates.pop_front();
+        }
+        minima.push(candidates[0]);
+    }
+    minima
+}
+
+impl Sampling {
+    fn rank(&self, index: usize) -> u64 {
+        match self.order {
+            Order::Lexicographic => index as u64,
+            Order::Hash => mix(index as u64),
+        }
+    }
+
+    // ranks returns the index and rank of every window of length len of a run
+    // of letters that are all in the alphabet.
+    fn ranks(&self, alphabet: &Alphabet, run: &[u8], len: usize) -> (Vec<usize>, Vec<u64>) {
+        alphabet
+            .windows(run, len)
+            .map(|(_, index)| (index, self.rank(index)))
+            .unzip()
+    }
+
+    /// sample returns the start and index of every sampled window of length
+    /// len of seq. Windows holding letters outside of the alphabet are never
+    /// sampled, and syncmer rings no longer than s keep every window.
+    pub fn sample(&self, alphabet: &Alphabet, seq: &[u8], len: usize) -> Vec<(usize, usize)> {
+        let mut sampled = Vec::new();
+        let mut offset = 0;
+        for run in seq.split(|l| alphabet.code(*l).is_none()) {
+            self.sample_run(alphabet, run, len, |start, index| {
+                sampled.push((offset + start, index))
+            });
+            offset += run.len() + 1;
+        }
+        sampled
+    }
+
+    fn sample_run<F: FnMut(usize, usize)>(
+        &self,
+        alphabet: &Alphabet,
+        run: &[u8],
+        len: usize,
+        mut keep: F,
+    ) {
+        let (indexes, ranks) = self.ranks(alphabet, run, len);
+        match self.scheme {
+            Scheme::Minimizer { w } => {
+                // a run shorter than w windows still has one minimizer.
+                let w = w.min(ranks.len());
+                let mut last = None;
+                for start in window_minima(&ranks, w.max(1)) {
+                    if last != Some(start) {
+                        keep(start, indexes[start]);
+                        last = Some(start);
+                    }
+                }
+            }
+            Scheme::OpenSyncmer { s } | Scheme::ClosedSyncmer { s } if s >= len => {
+                for (start, index) in indexes.into_iter().enumerate() {
+                    keep(start, index);
+                }
+            }
+            Scheme::OpenSyncmer { s } | Scheme::ClosedSyncmer { s } => {
+                let (_, substrings) = self.ranks(alphabet, run, s);
+                let smallest = window_minima(&substrings, len - s + 1);
+                let closed = matches!(self.scheme, Scheme::ClosedSyncmer { .. });
+                for (start, index) in indexes.into_iter().enumerate() {
+                    let at = smallest[start];
+                    if at == start || (closed && at == start + len - s) {
+                        keep(start, index);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Scheme {
+    type Err = Error;
+
+    /// from_str parses minimizer:W, open-syncmer:S or closed-syncmer:S.
+    fn from_str(spec: &str) -> Result<Scheme> {
+        let invalid = || {
+            Error::InvalidSampling(format!(
+                "{:?} is not minimizer:W, open-syncmer:S or closed-syncmer:S",
+                spec
+            ))
+        };
+        let (name, n) = spec.split_once(':').ok_or_else(invalid)?;
+        let n: usize = n.parse().map_err(|_| invalid())?;
+        if n == 0 {
+            return Err(Error::InvalidSampling(format!(
+                "{:?} must be positive",
+                spec
+            )));
+        }
+        match name {
+            "minimizer" => Ok(Scheme::Minimizer { w: n }),
+            "open-syncmer" => Ok(Scheme::OpenSyncmer { s: n }),
+            "closed-syncmer" => Ok(Scheme::ClosedSyncmer { s: n }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Scheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scheme::Minimizer { w } => write!(f, "minimizer:{}", w),
+            Scheme::OpenSyncmer { s } => write!(f, "open-syncmer:{}", s),
+            Scheme::ClosedSyncmer { s } => write!(f, "closed-syncmer:{}", s),
+        }
+    }
+}
+
+impl FromStr for Order {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Order> {
+        match s {
+            "lexicographic" => Ok(Order::Lexicographic),
+            "hash" => Ok(Order::Hash),
+            _ => Err(Error::InvalidSampling(format!(
+                "order {:?} is not lexicographic or hash",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Order {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Order::Lexicographic => f.write_str("lexicographic"),
+            Order::Hash => f.write_str("hash"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sampled(sampling: Sampling, seq: &[u8], len: usize) -> Vec<(usize, String)> {
+        let dna = Alphabet::dna();
+        sampling
+            .sample(&dna, seq, len)
+            .into_iter()
+            .map(|(start, index)| (start, dna.index_to_seq(index)))
+            .collect()
+    }
+
+    fn owned(expected: Vec<(usize, &str)>) -> Vec<(usize, String)> {
+        expected
+            .into_iter()
+            .map(|(s, k)| (s, k.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_window_minima() {
+        assert_eq!(vec![1, 1, 3, 3], window_minima(&[5, 1, 4, 0, 2], 2));
+        assert_eq!(vec![0, 1, 2], window_minima(&[3, 2, 1], 1));
+        assert_eq!(vec![0], window_minima(&[1, 1, 1], 3));
+    }
+
+    #[test]
+    fn test_minimizers() -> Result<()> {
+        let sampling = Sampling {
+            scheme: "minimizer:2".parse()?,
+            order: Order::Lexicographic,
+        };
+        // the 2-mers tg, gc, ca and ac give windows [tg gc] [gc ca] [ca ac].
+        let expected = owned(vec![(1, "gc"), (2, "ca"), (3, "ac")]);
+        assert_eq!(expected, sampled(sampling, b"tgcac", 2));
+        // runs are sampled apart, and a short run keeps its smallest window.
+        let expected = owned(vec![(0, "ga"), (3, "ac")]);
+        assert_eq!(expected, sampled(sampling, b"gaNac", 2));
+
+        let hashed = Sampling {
+            order: Order::Hash,
+            ..sampling
+        };
+        // the hash order is fixed, and ranks ca below ac, gc and tg.
+        let expected = owned(vec![(1, "gc"), (2, "ca")]);
+        assert_eq!(expected, sampled(hashed, b"tgcac", 2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_syncmers() -> Result<()> {
+        let open = Sampling {
+            scheme: "open-syncmer:1".parse()?,
+            order: Order::Lexicographic,
+        };
+        // the 3-mers of gatca are gat, atc and tca, whose smallest letter is
+        // at offset 1, 0 and 2.
+        assert_eq!(owned(vec![(1, "atc")]), sampled(open, b"gatca", 3));
+        let closed = Sampling {
+            scheme: "closed-syncmer:1".parse()?,
+            ..open
+        };
+        let expected = owned(vec![(1, "atc"), (2, "tca")]);
+        assert_eq!(expected, sampled(closed, b"gatca", 3));
+        // rings no longer than s keep every window.
+        assert_eq!(5, sampled(closed, b"gatca", 1).len());
+
+        assert!("minimizer".parse::<Scheme>().is_err());
+        assert!("syncmer:3".parse::<Scheme>().is_err());
+        assert!("open-syncmer:0".parse::<Scheme>().is_err());
+        assert_eq!(
+            "closed-syncmer:4",
+            "closed-syncmer:4".parse::<Scheme>()?.to_string()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+// scan walks the records of a fasta file and looks up, at every position, the
+// count of the window of one length starting there. Low counts mark unique
+// regions and high counts repeats, and a count of one is where reads of that
+// length map uniquely. Tracks are written as bedGraph or fixedStep wiggle, which
+// bedGraphToBigWig and wigToBigWig turn into bigWig.
+
+use crate::build::reader;
+use crate::database::Database;
+use crate::error::{Error, Result};
+use clap::ArgEnum;
+use std::io::Write;
+use tracing::info;
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq)]
+pub enum TrackFormat {
+    // Bedgraph merges runs of positions with the same count into one line.
+    Bedgraph,
+    // Wiggle writes one count per line, in a fixedStep block per run of
+    // positions that have a count.
+    Wiggle,
+}
+
+// windows iterates the start and index of the window of length len starting at
+// every position of seq, skipping windows that run off the end of seq or hold
+// letters outside of the alphabet of the index. Spaced seed indexes look up
+// the key of the window of the seed starting there.
+pub(crate) fn windows<'a>(
+    m: &'a Database,
+    seq: &'a [u8],
+    len: usize,
+) -> Result<Box<dyn Iterator<Item = (usize, usize)> + 'a>> {
+    m.check_seqlen(len)?;
+    let header = m.header();
+    Ok(match &header.seed {
+        Some(seed) => {
+            seed.check_len(len)?;
+            Box::new(seed.windows(&header.alphabet, seq, len))
+        }
+        None => Box::new(header.alphabet.windows(seq, len)),
+    })
+}
+
+// counts returns the count of the window of length len starting at every
+// position of seq, or None where windows skips it.
+pub fn counts(m: &Database, seq: &[u8], len: usize) -> Result<Vec<Option<u32>>> {
+    let mut counts = vec![None; seq.len()];
+    for (start, index) in windows(m, seq, len)? {
+        counts[start] = Some(m[index]);
+    }
+    Ok(counts)
+}
+
+pub fn write_bedgraph<W: Write>(out: &mut W, chrom: &str, counts: &[Option<u32>]) -> Result<()> {
+    let mut start = 0;
+    while start < counts.len() {
+        let count = counts[start];
+        let end = start + counts[start..].iter().take_while(|c| **c == count).count();
+        if let Some(count) = count {
+            writeln!(out, "{}\t{}\t{}\t{}", chrom, start, end, count).map_err(Error::Output)?;
+        }
+        start = end;
+    }
+    Ok(())
+}
+
+pub fn write_wiggle<W: Write>(out: &mut W, chrom: &str, counts: &[Option<u32>]) -> Result<()> {
+    let mut in_block = false;
+    for (i, count) in counts.iter().enumerate() {
+        match count {
+            Some(count) => {
+                // wiggle positions start at 1.
+                if !in_block {
+                    writeln!(out, "fixedStep chrom={} start={} step=1", chrom, i + 1)
+                        .map_err(Error::Output)?;
+                    in_block = true;
+                }
+                writeln!(out, "{}", count).map_err(Error::Output)?;
+            }
+            None => in_block = false,
+        }
+    }
+    Ok(())
+}
+
+// scan writes the track of every record of the fasta file to out.
+pub fn scan<W: Write>(
+    m: &Database,
+    fasta_file: &str,
+    len: usize,
+    format: TrackFormat,
+    out: &mut W,
+) -> Result<()> {
+    let track = match format {
+        TrackFormat::Bedgraph => "bedGraph",
+        TrackFormat::Wiggle => "wiggle_0",
+    };
+    writeln!(out, "track type={} name=\"hustar seqlen={}\"", track, len).map_err(Error::Output)?;
+    for record in reader(fasta_file)?.records() {
+        let record = record.map_err(|e| Error::Fasta(format!("{}: {}", fasta_file, e)))?;
+        info!("scanning record_id={} with seqlen={}", record.id(), len);
+        let counts = counts(m, record.seq(), len)?;
+        match format {
+            TrackFormat::Bedgraph => write_bedgraph(out, record.id(), &counts)?,
+            TrackFormat::Wiggle => write_wiggle(out, record.id(), &counts)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::alphabet::Alphabet;
+    use crate::database::{temp_build, temp_fasta};
+    use crate::header::Header;
+
+    #[test]
+    fn test_scan() -> Result<()> {
+        let records = ">one\nACACNGT\n>two\nCA\n";
+        let m = temp_build(records, Header::new(Alphabet::dna(), 2));
+        let fasta = temp_fasta(records);
+        let fasta = fasta.path().to_str().unwrap();
+
+        // ac twice and ca twice, across both records.
+        let expected = vec![Some(2), Some(2), Some(2), None, None, Some(1), None];
+        assert_eq!(expected, counts(&m, b"ACACNGT", 2)?);
+        assert!(counts(&m, b"ACAC", 3).is_err());
+
+        let mut out = Vec::new();
+        scan(&m, fasta, 2, TrackFormat::Bedgraph, &mut out)?;
+        assert_eq!(
+            "track type=bedGraph name=\"hustar seqlen=2\"\n\
+             one\t0\t3\t2\none\t5\t6\t1\ntwo\t0\t1\t2\n",
+            String::from_utf8(out).unwrap()
+        );
+
+        let mut out = Vec::new();
+        scan(&m, fasta, 2, TrackFormat::Wiggle, &mut out)?;
+        assert_eq!(
+            "track type=wiggle_0 name=\"hustar seqlen=2\"\n\
+             fixedStep chrom=one start=1 step=1\n2\n2\n2\n\
+             fixedStep chrom=one start=6 step=1\n1\n\
+             fixedStep chrom=two start=1 step=1\n2\n",
+            String::from_utf8(out).unwrap()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,165 @@
+//! Spaced seeds, which only count the care positions of every window.
+
+use crate::alphabet::Alphabet;
+use crate::error::{Error, Result};
+use std::fmt;
+use std::str::FromStr;
+
+/// Seed is a mask such as 1101101 laid over windows of its length, where 1
+/// marks a care position and 0 a position that is ignored. The key of a window
+/// is its care positions read in order, and ring l of a seeded index holds the
+/// keys of the first l care positions, so inner rings are prefixes of the outer
+/// ones as in a contiguous index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Seed {
+    mask: String,
+    // care holds the offset of every care position within the window.
+    care: Vec<usize>,
+}
+
+impl Seed {
+    pub fn new(mask: &str) -> Result<Seed> {
+        if let Some(l) = mask.chars().find(|l| *l != '0' && *l != '1') {
+            return Err(Error::InvalidSeed(format!(
+                "{:?} holds {:?} but only 0 and 1 are allowed",
+                mask, l
+            )));
+        }
+        if !mask.starts_with('1') || !mask.ends_with('1') {
+            return Err(Error::InvalidSeed(format!(
+                "{:?} must start and end with a care position",
+                mask
+            )));
+        }
+        let care = mask
+            .bytes()
+            .enumerate()
+            .filter(|(_, l)| *l == b'1')
+            .map(|(i, _)| i)
+            .collect();
+        Ok(Seed {
+            mask: mask.to_string(),
+            care,
+        })
+    }
+
+    /// weight is the number of care positions, which is the length of a key.
+    pub fn weight(&self) -> usize {
+        self.care.len()
+    }
+
+    /// span is the length of the windows the seed is laid over.
+    pub fn span(&self) -> usize {
+        self.mask.len()
+    }
+
+    /// window_len is the length of the windows that keys of len are read
+    /// from, which reach from the first to the len-th care position.
+    pub fn window_len(&self, len: usize) -> usize {
+        self.care[..len.min(self.weight())]
+            .last()
+            .map_or(0, |i| i + 1)
+    }
+
+    /// check_len fails unless keys of len fit the seed.
+    pub fn check_len(&self, len: usize) -> Result<()> {
+        if len > self.weight() {
+            return Err(Error::InvalidSeed(format!(
+                "{} has {} care positions which is too few for seqlen={}",
+                self.mask,
+                self.weight(),
+                len
+            )));
+        }
+        Ok(())
+    }
+
+    /// key returns the care positions of a full window, or None when window
+    /// is not as long as the seed.
+    pub fn key(&self, window: &str) -> Option<String> {
+        let window = window.as_bytes();
+        if window.len() != self.span() {
+            return None;
+        }
+        let key = self.care.iter().map(|i| window[*i] as char).collect();
+        Some(key)
+    }
+
+    /// windows iterates the start and index of the key of length len of every
+    /// window of seq. Windows whose care positions hold letters outside of the
+    /// alphabet are skipped, the ignored positions may hold anything.
+    pub fn windows<'a>(
+        &'a self,
+        alphabet: &'a Alphabet,
+        seq: &'a [u8],
+        len: usize,
+    ) -> impl Iterator<Item = (usize, usize)> + 'a {
+        let care = &self.care[..len.min(self.weight())];
+        let span = self.window_len(len);
+        let base = alphabet.base_index(len);
+        let starts = if span == 0 {
+            0
+        } else {
+            (seq.len() + 1).saturating_sub(span)
+        };
+        (0..starts).filter_map(move |start| {
+            let mut addr = 0;
+            for i in care {
+                addr = addr * alphabet.radix() + alphabet.code(seq[start + i])?;
+            }
+            Some((start, base + addr))
+        })
+    }
+}
+
+impl FromStr for Seed {
+    type Err = Error;
+    fn from_str(mask: &str) -> Result<Seed> {
+        Seed::new(mask)
+    }
+}
+
+impl fmt::Display for Seed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.mask)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_seed() -> Result<()> {
+        let seed: Seed = "1101101".parse()?;
+        assert_eq!((5, 7), (seed.weight(), seed.span()));
+        assert_eq!((4, 7), (seed.window_len(3), seed.window_len(5)));
+        assert_eq!(Some("acgtc".to_string()), seed.key("acNgtNc"));
+        assert_eq!(None, seed.key("acgt"));
+        assert!(seed.check_len(5).is_ok());
+        assert!(seed.check_len(6).is_err());
+        assert!("0101".parse::<Seed>().is_err());
+        assert!("1100".parse::<Seed>().is_err());
+        assert!("1121".parse::<Seed>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_windows() -> Result<()> {
+        let dna = Alphabet::dna();
+        let seed: Seed = "101".parse()?;
+        let keys: Vec<(usize, String)> = seed
+            .windows(&dna, b"acgNtta", 2)
+            .map(|(start, index)| (start, dna.index_to_seq(index)))
+            .collect();
+        let expected: Vec<(usize, String)> = vec![(0, "ag"), (2, "gt"), (4, "ta")]
+            .into_iter()
+            .map(|(s, k)| (s, k.to_string()))
+            .collect();
+        assert_eq!(expected, keys);
+
+        // the first ring only looks at the first care position.
+        assert_eq!(6, seed.windows(&dna, b"acgNtta", 1).count());
+        Ok(())
+    }
+}
@@ -0,0 +1,406 @@
+//! Count-Min sketches, which estimate the count of every sequence of length
+//! 1..=seqlen in a fixed amount of memory, for seqlens no dense index can hold.
+//! A sketch is depth rows of width u32 counters. Every sequence adds one to a
+//! counter of each row picked by a hash of its own, and its estimate is the
+//! smallest of those counters. Estimates never fall below the true count, and
+//! with probability 1 - delta exceed it by at most epsilon times the number of
+//! sequences counted, where epsilon = e / width and delta = exp(-depth).
+//!
+//! The file starts with the header of an index whose metadata holds
+//! sketch=count-min, the width and depth and the resulting bounds, followed by
+//! the counters row by row.
+
+use crate::alphabet::Alphabet;
+use crate::build::reader;
+use crate::database::{CountSource, DatabaseMut};
+use crate::error::{io_error, Error, Result};
+use crate::header::Header;
+use crate::sample::mix;
+use core::ops::Index;
+use memmap2::Mmap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::info;
+
+// SEED is the hash of the empty sequence.
+const SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+// push folds the next symbol into the hash of a sequence.
+fn push(hash: u64, code: usize) -> u64 {
+    mix(hash.wrapping_mul(0x0000_0100_0000_01b3) ^ (code as u64 + 1))
+}
+
+// column picks the counter of a sequence in row.
+fn column(hash: u64, row: usize, width: usize) -> usize {
+    (mix(hash.wrapping_add((row as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15))) % width as u64)
+        as usize
+}
+
+/// Sketch is a read only Count-Min sketch. Clones share the same mapping.
+#[derive(Clone)]
+pub struct Sketch {
+    mmap: Arc<Mmap>,
+    header: Arc<Header>,
+    offset: usize,
+    width: usize,
+    depth: usize,
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidSketch(reason.to_string())
+}
+
+/// build counts every sequence of length 1..=seqlen of every record of the
+/// fasta file into a new sketch of width and depth at outpath. Windows holding
+/// letters outside of the alphabet are skipped.
+pub fn build<P: AsRef<Path>>(
+    fasta_file: &str,
+    outpath: P,
+    header: Header,
+    width: usize,
+    depth: usize,
+) -> Result<()> {
+    use std::io::{BufWriter, Write};
+
+    let seqlen = header.seqlen.ok_or_else(|| invalid("missing seqlen"))?;
+    if header.seed.is_some() || header.sampling.is_some() {
+        return Err(invalid(
+            "spaced seeds and sampling are not supported by sketches",
+        ));
+    }
+    if width == 0 || depth == 0 {
+        return Err(invalid("width and depth must be positive"));
+    }
+    info!(
+        "building sketch from fasta_file={} seqlen={} alphabet={} width={} depth={}",
+        fasta_file, seqlen, header.alphabet, width, depth
+    );
+    let alphabet = &header.alphabet;
+    let mut table = vec![0_u32; width * depth];
+    let mut total: u64 = 0;
+    for record in reader(fasta_file)?.records() {
+        let record = record.map_err(|e| Error::Fasta(format!("{}: {}", fasta_file, e)))?;
+        info!("sketching record_id={}", record.id());
+        let seq = record.seq();
+        for start in 0..seq.len() {
+            let mut hash = SEED;
+            for l in &seq[start..(start + seqlen).min(seq.len())] {
+                let code = match alphabet.code(*l) {
+                    Some(code) => code,
+                    None => break,
+                };
+                hash = push(hash, code);
+                for row in 0..depth {
+                    let c = &mut table[row * width + column(hash, row, width)];
+                    *c = c.saturating_add(1);
+                }
+                total += 1;
+            }
+        }
+    }
+
+    let epsilon = std::f64::consts::E / width as f64;
+    let mut header = header;
+    for (key, value) in [
+        ("sketch", "count-min".to_string()),
+        ("width", width.to_string()),
+        ("depth", depth.to_string()),
+        ("epsilon", epsilon.to_string()),
+        ("delta", (-(depth as f64)).exp().to_string()),
+        ("total", total.to_string()),
+        ("error", (epsilon * total as f64).to_string()),
+    ] {
+        header.metadata.insert(key.to_string(), value);
+    }
+    info!("counted total={} sequences", total);
+
+    let path = outpath.as_ref();
+    let file = std::fs::File::create(path).map_err(io_error(path))?;
+    let mut out = BufWriter::new(file);
+    out.write_all(&header.encode()).map_err(io_error(path))?;
+    for c in table {
+        out.write_all(&c.to_ne_bytes()).map_err(io_error(path))?;
+    }
+    out.flush().map_err(io_error(path))?;
+    Ok(())
+}
+
+impl Sketch {
+    pub fn open<P: Into<PathBuf>>(path: P) -> Result<Sketch> {
+        let path = path.into();
+        let file = std::fs::File::open(&path).map_err(io_error(&path))?;
+        let mmap = unsafe { Mmap::map(&file).map_err(io_error(&path))? };
+        let (header, offset) = Header::decode(&mmap)?;
+        if header.metadata.get("sketch").map(|s| s.as_str()) != Some("count-min") {
+            return Err(invalid("not a count-min sketch"));
+        }
+        let number = |key: &str| -> Result<usize> {
+            header
+                .metadata
+                .get(key)
+                .and_then(|n| n.parse().ok())
+                .ok_or_else(|| invalid(&format!("missing {}", key)))
+        };
+        let (width, depth) = (number("width")?, number("depth")?);
+        if width == 0 || depth == 0 {
+            return Err(invalid("width and depth must be positive"));
+        }
+        if mmap.len() < offset + width * depth * std::mem::size_of::<u32>() {
+            return Err(invalid("the file is truncated"));
+        }
+        Ok(Sketch {
+            mmap: Arc::new(mmap),
+            header: Arc::new(header),
+            offset,
+            width,
+            depth,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn alphabet(&self) -> &Alphabet {
+        &self.header.alphabet
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// epsilon is the error bound per sequence counted.
+    pub fn epsilon(&self) -> f64 {
+        std::f64::consts::E / self.width as f64
+    }
+
+    /// delta is the probability that an estimate exceeds the error bound.
+    pub fn delta(&self) -> f64 {
+        (-(self.depth as f64)).exp()
+    }
+
+    /// total is the number of sequences counted.
+    pub fn total(&self) -> u64 {
+        self.header
+            .metadata
+            .get("total")
+            .and_then(|t| t.parse().ok())
+            .unwrap_or(0)
+    }
+
+    /// check_seqlen fails unless the sketch counted sequences of length
+    /// 1..=max_seqlen.
+    pub fn check_seqlen(&self, max_seqlen: usize) -> Result<()> {
+        let seqlen = self.header.seqlen.unwrap_or(0);
+        if max_seqlen > seqlen {
+            return Err(Error::UnsupportedSeqlen {
+                seqlen: max_seqlen,
+                max: seqlen,
+                backend: "sketch",
+            });
+        }
+        Ok(())
+    }
+
+    fn table(&self) -> &[u32] {
+        let (_, table, _) = unsafe { self.mmap[self.offset..].align_to::<u32>() };
+        &table[..self.width * self.depth]
+    }
+
+    // slot returns the position of the smallest counter of the sequence with
+    // hash.
+    fn slot(&self, hash: u64) -> usize {
+        let table = self.table();
+        (0..self.depth)
+            .map(|row| row * self.width + column(hash, row, self.width))
+            .min_by_key(|i| table[*i])
+            .expect("depth is positive")
+    }
+
+    fn hash(&self, seq: &str) -> Result<u64> {
+        let seqlen = self.header.seqlen.unwrap_or(0);
+        if seq.len() > seqlen {
+            return Err(Error::TooLong {
+                seq: seq.to_string(),
+                len: seq.len(),
+                max: seqlen,
+            });
+        }
+        seq.chars().try_fold(SEED, |hash, l| {
+            let code = u8::try_from(l)
+                .ok()
+                .and_then(|l| self.alphabet().code(l))
+                .ok_or_else(|| Error::InvalidSymbol {
+                    letter: l,
+                    seq: seq.to_string(),
+                    alphabet: self.alphabet().name().to_string(),
+                })?;
+            Ok(push(hash, code))
+        })
+    }
+
+    // index_hash returns the hash of the sequence at index of a dense index.
+    fn index_hash(&self, index: usize) -> u64 {
+        let radix = self.alphabet().radix();
+        let (mut base, mut ring, mut len) = (0, radix, 1);
+        while index - base >= ring {
+            base += ring;
+            ring = ring.saturating_mul(radix);
+            len += 1;
+        }
+        let mut addr = index - base;
+        let mut codes = vec![0; len];
+        for code in codes.iter_mut().rev() {
+            *code = addr % radix;
+            addr /= radix;
+        }
+        codes.iter().fold(SEED, |hash, code| push(hash, *code))
+    }
+
+    /// get returns the estimated count of seq.
+    pub fn get(&self, seq: &str) -> Result<u32> {
+        Ok(self.table()[self.slot(self.hash(seq)?)])
+    }
+
+    /// to_database writes the estimates of every sequence of length
+    /// 1..=seqlen as a dense index at path, which every renderer reads. The
+    /// bounds of the sketch are kept in its metadata.
+    pub fn to_database<P: Into<PathBuf>>(&self, path: P, seqlen: usize) -> Result<()> {
+        self.check_seqlen(seqlen)?;
+        let mut header = Header::new(self.alphabet().clone(), seqlen);
+        header
+            .metadata
+            .insert("approximate".to_string(), "count-min".to_string());
+        for key in ["width", "depth", "epsilon", "delta", "total", "error"] {
+            if let Some(value) = self.header.metadata.get(key) {
+                header.metadata.insert(key.to_string(), value.clone());
+            }
+        }
+        let mut db = DatabaseMut::create_with(path, header)?;
+        for index in 0..self.alphabet().base_index(seqlen + 1) {
+            db[index] = self[index];
+        }
+        Ok(())
+    }
+}
+
+impl Index<&str> for Sketch {
+    type Output = u32;
+
+    /// index returns the smallest counter of seq, panicking where get fails.
+    fn index(&self, seq: &str) -> &Self::Output {
+        let hash = self.hash(seq).unwrap_or_else(|e| panic!("{}", e));
+        &self.table()[self.slot(hash)]
+    }
+}
+
+impl Index<usize> for Sketch {
+    type Output = u32;
+
+    /// index returns the smallest counter of the sequence at index of a dense
+    /// index, which is how renderers walk the sketch.
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.table()[self.slot(self.index_hash(index))]
+    }
+}
+
+impl CountSource for Sketch {
+    fn alphabet(&self) -> &Alphabet {
+        Sketch::alphabet(self)
+    }
+
+    // check_seqlen also fails for sequences whose index does not fit a usize,
+    // which get still estimates.
+    fn check_seqlen(&self, max_seqlen: usize) -> Result<()> {
+        self.alphabet().check_index_len(max_seqlen)?;
+        Sketch::check_seqlen(self, max_seqlen)
+    }
+
+    fn get(&self, seq: &str) -> Result<u32> {
+        Sketch::get(self, seq)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::alphabet::Alphabet;
+    use crate::database::Database;
+    use tempfile::NamedTempFile;
+
+    // fasta writes the records the tests sketch to a temporary file of each
+    // test's own.
+    fn fasta() -> NamedTempFile {
+        let fasta = NamedTempFile::new().unwrap();
+        std::fs::write(fasta.path(), ">one\nACGNAC\n>two\nac\n").unwrap();
+        fasta
+    }
+
+    #[test]
+    fn test_sketch() -> Result<()> {
+        let (fasta, path) = (fasta(), NamedTempFile::new().unwrap());
+        let path = path.path();
+        // 40 is longer than a dense dna index can hold.
+        build(
+            fasta.path().to_str().unwrap(),
+            path,
+            Header::new(Alphabet::dna(), 40),
+            1 << 12,
+            4,
+        )?;
+        let s = Sketch::open(path)?;
+        assert_eq!((1 << 12, 4), (s.width(), s.depth()));
+        assert_eq!(12, s.total());
+        assert_eq!(3, s.get("a")?);
+        assert_eq!(3, s["AC"]);
+        assert_eq!(1, s.get("cg")?);
+        assert_eq!(0, s.get("tttt")?);
+        assert!(matches!(s.get("ab"), Err(Error::InvalidSymbol { .. })));
+        assert!(matches!(s.get(&"a".repeat(41)), Err(Error::TooLong { .. })));
+        assert!(s.check_seqlen(40).is_ok());
+        assert!(Database::open(path).is_err());
+
+        let index = NamedTempFile::new().unwrap();
+        let index = index.path();
+        s.to_database(index, 3)?;
+        let m = Database::open(index)?;
+        assert_eq!(
+            Some("count-min"),
+            m.header().metadata.get("approximate").map(|s| s.as_str())
+        );
+        for seq in ["a", "c", "ac", "cg", "acg", "gac"] {
+            assert_eq!(s.get(seq)?, m.get(seq)?, "{}", seq);
+        }
+        assert!(s.to_database(index, 41).is_err());
+
+        // renderers read the sketch as they read the index it exports.
+        assert_eq!(
+            crate::render::ring_maxes(&m, 3).unwrap(),
+            crate::render::ring_maxes(&s, 3).unwrap()
+        );
+        assert!(CountSource::check_seqlen(&s, 40).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_collisions() -> Result<()> {
+        // a single counter per row holds every sequence, the bound of
+        // epsilon times total.
+        let (fasta, path) = (fasta(), NamedTempFile::new().unwrap());
+        let fasta = fasta.path().to_str().unwrap();
+        build(fasta, path.path(), Header::new(Alphabet::dna(), 2), 1, 2)?;
+        let s = Sketch::open(path.path())?;
+        assert_eq!(s.total() as u32, s.get("gg")?);
+        let plain = NamedTempFile::new().unwrap();
+        DatabaseMut::create(plain.path(), 2)?;
+        assert!(matches!(
+            Sketch::open(plain.path()),
+            Err(Error::InvalidSketch(_))
+        ));
+        Ok(())
+    }
+}
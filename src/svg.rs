@@ -0,0 +1,248 @@
+// svg renders the star as vector wedges instead of pixels. Each ring holds one
+// seqlen and each wedge covers a contiguous range of the mmap index, so the
+// output stays sharp at any zoom level.
+
+use crate::accumulator::Accumulator;
+use crate::alphabet::base_index;
+use crate::annotate::{margins, write_svg_overlay, Legend};
+use crate::database::{index_to_seq, Database};
+use crate::error::{Error, Result};
+use crate::kmer::RADIX;
+use crate::motif::{self, MatchMode, Motif};
+use crate::traverse::{index_theta, ring_radii};
+use std::fmt::Write as _;
+use std::io::Write;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Wedge {
+    pub seqlen: usize,
+    pub gte: usize,
+    pub lt: usize,
+    pub count: u64,
+    // motif is the first highlighted motif matching a sequence of the wedge.
+    pub motif: Option<usize>,
+}
+
+impl Wedge {
+    // label names the sequences under the wedge. A wedge covering a single
+    // sequence is labeled with it, merged wedges share a prefix and are labeled
+    // with that prefix followed by '*'.
+    pub fn label(&self) -> String {
+        let seq = index_to_seq(self.gte);
+        let merged = self.lt - self.gte;
+        if merged == 1 {
+            return seq;
+        }
+        let suffix = (merged.trailing_zeros() / 2) as usize;
+        format!("{}*", &seq[..self.seqlen - suffix])
+    }
+}
+
+// make_wedges splits every ring from 1..=max_length into wedges. Rings with more
+// than max_wedges sequences merge runs of 4^n neighbouring sequences so that each
+// wedge covers every sequence sharing a prefix.
+pub fn make_wedges(max_length: usize, max_wedges: usize) -> Vec<Wedge> {
+    let mut wedges = Vec::new();
+    for seqlen in 1..=max_length {
+        let max: usize = 1 << (seqlen * 2);
+        let mut merge: usize = 1;
+        while max / merge > max_wedges.max(4) {
+            merge <<= 2;
+        }
+        let base = base_index(RADIX, seqlen);
+        for addr in (0..max).step_by(merge) {
+            wedges.push(Wedge {
+                seqlen,
+                gte: base + addr,
+                lt: base + addr + merge,
+                count: 0,
+                motif: None,
+            });
+        }
+    }
+    wedges
+}
+
+// count_wedges fills in the count of every wedge. Wedges are expected in the
+// order make_wedges produces them so that the accumulator only walks forward.
+pub fn count_wedges(m: &Database, wedges: &mut [Wedge]) {
+    let mut acc = Accumulator::default();
+    for wedge in wedges.iter_mut() {
+        wedge.count = acc.sum_to(m, wedge.gte, wedge.lt);
+    }
+}
+
+// highlight_wedges marks every wedge holding a sequence that matches one of the
+// motifs.
+pub fn highlight_wedges(wedges: &mut [Wedge], motifs: &[Motif], mode: MatchMode) {
+    for wedge in wedges.iter_mut() {
+        wedge.motif = motif::first_match(motifs, mode, wedge.seqlen, wedge.gte, wedge.lt);
+    }
+}
+
+// wedge_path draws the annulus sector between the inner and outer radius of the
+// ring that holds the wedge.
+fn wedge_path(wedge: &Wedge, max_length: usize, side_length: usize) -> String {
+    let c = side_length as f64 / 2.0;
+    let (inner, outer) = ring_radii(wedge.seqlen, max_length);
+    let (inner, outer) = (inner * c, outer * c);
+    let t1 = index_theta(RADIX, wedge.seqlen, wedge.gte);
+    let t2 = index_theta(RADIX, wedge.seqlen, wedge.lt);
+    // make_points uses x = width/2 - w and y = height/2 - h, so angles are
+    // mirrored on both axes when converted back into image coordinates.
+    let at = |r: f64, t: f64| (c - r * t.cos(), c - r * t.sin());
+    let large = if t2 - t1 > std::f64::consts::PI { 1 } else { 0 };
+    let (ox1, oy1) = at(outer, t1);
+    let (ox2, oy2) = at(outer, t2);
+    let (ix2, iy2) = at(inner, t2);
+    let (ix1, iy1) = at(inner, t1);
+    let mut d = String::new();
+    write!(d, "M{:.3},{:.3}", ox1, oy1).unwrap();
+    write!(
+        d,
+        "A{:.3},{:.3} 0 {} 1 {:.3},{:.3}",
+        outer, outer, large, ox2, oy2
+    )
+    .unwrap();
+    write!(d, "L{:.3},{:.3}", ix2, iy2).unwrap();
+    if inner > 0.0 {
+        write!(
+            d,
+            "A{:.3},{:.3} 0 {} 0 {:.3},{:.3}",
+            inner, inner, large, ix1, iy1
+        )
+        .unwrap();
+    }
+    d.push('Z');
+    d
+}
+
+fn ring_maxes(wedges: &[Wedge], max_length: usize) -> Vec<u64> {
+    let mut maxes: Vec<u64> = vec![0; max_length + 1];
+    for wedge in wedges {
+        maxes[wedge.seqlen] = maxes[wedge.seqlen].max(wedge.count);
+    }
+    maxes
+}
+
+// ring_max returns the largest wedge count of the ring holding seqlen.
+pub fn ring_max(wedges: &[Wedge], seqlen: usize) -> u64 {
+    ring_maxes(wedges, seqlen)[seqlen]
+}
+
+// write_svg writes the wedges as an svg document. Each ring is normalized by its
+// own maximum count and the intensity follows the same curve as the png output.
+// A legend adds a title band above the star and a colorbar band below it.
+pub fn write_svg<W: Write>(
+    out: &mut W,
+    wedges: &[Wedge],
+    max_length: usize,
+    side_length: usize,
+    legend: Option<&Legend>,
+) -> Result<()> {
+    let maxes = ring_maxes(wedges, max_length);
+    let (top, bottom) = match legend {
+        Some(_) => margins(side_length as u32),
+        None => (0, 0),
+    };
+    writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+        side_length,
+        side_length as u32 + top + bottom
+    ).map_err(Error::Output)?;
+    writeln!(out, r#"<rect width="100%" height="100%" fill="black"/>"#).map_err(Error::Output)?;
+    writeln!(out, r#"<g transform="translate(0,{})">"#, top).map_err(Error::Output)?;
+    for wedge in wedges {
+        let max = maxes[wedge.seqlen];
+        let p = if max == 0 {
+            0.0
+        } else {
+            wedge.count as f64 / max as f64
+        };
+        let l = p.sqrt().sqrt();
+        // highlighted wedges follow motif::highlight.
+        let [r, g, b] = match wedge.motif {
+            Some(i) => motif::color(i).map(|c| (c as f64 * (0.35 + 0.65 * l)) as u8),
+            None => [(l * 255.0) as u8; 3],
+        };
+        writeln!(
+            out,
+            r#"<path d="{}" fill="rgb({},{},{})"><title>{} count={}</title></path>"#,
+            wedge_path(wedge, max_length, side_length),
+            r,
+            g,
+            b,
+            wedge.label(),
+            wedge.count,
+        )
+        .map_err(Error::Output)?;
+    }
+    writeln!(out, "</g>").map_err(Error::Output)?;
+    if let Some(legend) = legend {
+        write_svg_overlay(out, legend, side_length as u32)?;
+    }
+    writeln!(out, "</svg>").map_err(Error::Output)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_make_wedges() {
+        let wedges = make_wedges(3, 16);
+        assert_eq!(4 + 16 + 16, wedges.len());
+        assert_eq!((0, 1), (wedges[0].gte, wedges[0].lt));
+        assert_eq!((4, 5), (wedges[4].gte, wedges[4].lt));
+        assert_eq!((20, 24), (wedges[20].gte, wedges[20].lt));
+        assert_eq!((80, 84), (wedges[35].gte, wedges[35].lt));
+    }
+
+    #[test]
+    fn test_label() {
+        let wedges = make_wedges(3, 16);
+        assert_eq!("a", wedges[0].label());
+        assert_eq!("ac", wedges[5].label());
+        assert_eq!("ca*", wedges[24].label());
+        assert_eq!("tt*", wedges[35].label());
+    }
+
+    #[test]
+    fn test_write_svg() -> Result<()> {
+        let mut wedges = make_wedges(1, 4);
+        wedges[1].count = 16;
+        wedges[2].count = 1;
+        let mut out = Vec::new();
+        write_svg(&mut out, &wedges, 1, 100, None)?;
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(4, out.matches("<path").count());
+        assert!(out.contains("<title>c count=16</title>"));
+        assert!(out.contains(r#"fill="rgb(255,255,255)""#));
+        assert!(out.contains(r#"fill="rgb(127,127,127)""#));
+        assert_eq!(16, ring_max(&wedges, 1));
+
+        let legend = Legend {
+            title: "index.bin".to_string(),
+            sector: Some(crate::traverse::Sector::full(1)),
+            symbols: "acgt".to_string(),
+            max_count: 16,
+            count_label: "count".to_string(),
+            keys: Vec::new(),
+        };
+        let mut out = Vec::new();
+        write_svg(&mut out, &wedges, 1, 100, Some(&legend))?;
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(r#"height="148""#));
+        assert!(out.contains(r#"<g transform="translate(0,12)">"#));
+        assert!(out.contains(">index.bin</text>"));
+
+        highlight_wedges(&mut wedges, &[Motif::parse("c")?], MatchMode::StartsWith);
+        let mut out = Vec::new();
+        write_svg(&mut out, &wedges, 1, 100, None)?;
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(r#"fill="rgb(255,64,64)"><title>c count=16"#));
+        Ok(())
+    }
+}
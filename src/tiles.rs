@@ -0,0 +1,241 @@
+// tiles exports the star as a pyramid of image tiles for deep zoom viewers.
+// Every zoom level renders a virtual image twice as large as the level above
+// it, and every tile only visits the points of its own window.
+
+use crate::database::Database;
+use crate::error::{image_error, io_error, Error, Result};
+use crate::layout::Pixel;
+use crate::render::{count_pixels, density_image, ring_maxes};
+use crate::traverse::{make_window_points, Sector};
+use clap::ArgEnum;
+use image::GrayImage;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq)]
+pub enum TileFormat {
+    // DeepZoom writes star.dzi and star_files/<level>/<col>_<row>.png.
+    Dzi,
+    // Xyz writes <z>/<x>/<y>.png where level z has 2^z by 2^z tiles.
+    Xyz,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tile {
+    // side is the side length of the virtual image of the tile's level.
+    pub side: u32,
+    pub left: u32,
+    pub top: u32,
+    pub width: u32,
+    pub height: u32,
+    pub path_level: u32,
+    pub col: u32,
+    pub row: u32,
+}
+
+impl Tile {
+    pub fn path(&self, format: TileFormat) -> PathBuf {
+        match format {
+            TileFormat::Dzi => PathBuf::from("star_files")
+                .join(self.path_level.to_string())
+                .join(format!("{}_{}.png", self.col, self.row)),
+            TileFormat::Xyz => PathBuf::from(self.path_level.to_string())
+                .join(self.col.to_string())
+                .join(format!("{}.png", self.row)),
+        }
+    }
+}
+
+// level_tiles splits a level whose virtual image has the given side into tiles
+// of at most tile_size pixels.
+fn level_tiles(side: u32, tile_size: u32, path_level: u32) -> Vec<Tile> {
+    let count = side.div_ceil(tile_size);
+    let mut tiles = Vec::new();
+    for col in 0..count {
+        for row in 0..count {
+            let (left, top) = (col * tile_size, row * tile_size);
+            tiles.push(Tile {
+                side,
+                left,
+                top,
+                width: tile_size.min(side - left),
+                height: tile_size.min(side - top),
+                path_level,
+                col,
+                row,
+            });
+        }
+    }
+    tiles
+}
+
+// check_pyramid returns the side of the finest level, tile_size * 2^max_zoom,
+// and fails unless tile_size is positive and the side fits in an i32, which
+// make_window_points centers pixels in.
+pub fn check_pyramid(tile_size: u32, max_zoom: u32) -> Result<u32> {
+    if tile_size == 0 {
+        return Err(Error::InvalidPyramid(
+            "tile_size must be positive".to_string(),
+        ));
+    }
+    match tile_size.checked_shl(max_zoom) {
+        Some(full) if full >> max_zoom == tile_size && full <= i32::MAX as u32 => Ok(full),
+        _ => Err(Error::InvalidPyramid(format!(
+            "max_zoom={} makes tiles of size {} span more than {} pixels",
+            max_zoom,
+            tile_size,
+            i32::MAX
+        ))),
+    }
+}
+
+// make_tiles lists every tile of the pyramid from the coarsest level to
+// max_zoom, where the finest level is tile_size * 2^max_zoom pixels wide.
+// tile_size and max_zoom must pass check_pyramid.
+pub fn make_tiles(format: TileFormat, tile_size: u32, max_zoom: u32) -> Vec<Tile> {
+    let mut tiles = Vec::new();
+    match format {
+        TileFormat::Dzi => {
+            // deep zoom levels halve the image down to a single pixel.
+            let full = tile_size << max_zoom;
+            let max_level = 32 - (full - 1).leading_zeros();
+            for level in 0..=max_level {
+                let side = full.div_ceil(1 << (max_level - level));
+                tiles.extend(level_tiles(side, tile_size, level));
+            }
+        }
+        TileFormat::Xyz => {
+            for z in 0..=max_zoom {
+                tiles.extend(level_tiles(tile_size << z, tile_size, z));
+            }
+        }
+    }
+    tiles
+}
+
+pub fn dzi_descriptor(tile_size: u32, max_zoom: u32) -> String {
+    let full = tile_size << max_zoom;
+    format!(
+        concat!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+            "\n",
+            r#"<Image xmlns="http://schemas.microsoft.com/deepzoom/2008" Format="png" Overlap="0" TileSize="{}">"#,
+            "\n",
+            r#"  <Size Width="{}" Height="{}"/>"#,
+            "\n</Image>\n"
+        ),
+        tile_size, full, full
+    )
+}
+
+pub fn render_tile(m: &Database, sector: Sector, ring_maxes: &[u32], tile: &Tile) -> GrayImage {
+    let pixels: Vec<Pixel> = make_window_points(
+        tile.side,
+        tile.side,
+        sector,
+        tile.left,
+        tile.top,
+        tile.width,
+        tile.height,
+    )
+    .iter()
+    .map(Pixel::from)
+    .collect();
+    let counts = count_pixels(
+        m,
+        pixels.clone(),
+        tile.width as usize,
+        tile.height as usize,
+        sector.max_seqlen,
+    );
+    density_image(&pixels, &counts, ring_maxes)
+}
+
+pub fn export<P: AsRef<Path>>(
+    m: &Database,
+    sector: Sector,
+    format: TileFormat,
+    tile_size: u32,
+    max_zoom: u32,
+    output_dir: P,
+) -> Result<()> {
+    check_pyramid(tile_size, max_zoom)?;
+    let output_dir = output_dir.as_ref();
+    let ring_maxes = ring_maxes(m, sector.max_seqlen)?;
+    let tiles = make_tiles(format, tile_size, max_zoom);
+    info!(
+        "exporting {} tiles as {:?} into {:?}",
+        tiles.len(),
+        format,
+        output_dir
+    );
+    for (i, tile) in tiles.iter().enumerate() {
+        let path = output_dir.join(tile.path(format));
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(io_error(parent))?;
+        }
+        render_tile(m, sector, &ring_maxes, tile)
+            .save(&path)
+            .map_err(image_error(&path))?;
+        info!("wrote tile {} of {} to {:?}", i + 1, tiles.len(), path);
+    }
+    if format == TileFormat::Dzi {
+        let path = output_dir.join("star.dzi");
+        fs::write(&path, dzi_descriptor(tile_size, max_zoom)).map_err(io_error(&path))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::temp_database;
+
+    #[test]
+    fn test_make_tiles() {
+        let tiles = make_tiles(TileFormat::Xyz, 256, 2);
+        assert_eq!(1 + 4 + 16, tiles.len());
+        let last = tiles.last().unwrap();
+        assert_eq!(
+            (1024, 768, 768, 2, 3, 3),
+            (
+                last.side,
+                last.left,
+                last.top,
+                last.path_level,
+                last.col,
+                last.row
+            )
+        );
+        assert_eq!(PathBuf::from("2/3/3.png"), last.path(TileFormat::Xyz));
+
+        let tiles = make_tiles(TileFormat::Dzi, 4, 1);
+        let sides: Vec<u32> = tiles.iter().map(|t| t.side).collect();
+        assert_eq!(vec![1, 2, 4, 8, 8, 8, 8], sides);
+        assert_eq!(
+            PathBuf::from("star_files/3/1_1.png"),
+            tiles.last().unwrap().path(TileFormat::Dzi)
+        );
+        assert!(dzi_descriptor(4, 1).contains(r#"<Size Width="8" Height="8"/>"#));
+
+        assert_eq!(1 << 30, check_pyramid(1, 30).unwrap());
+        assert!(check_pyramid(1, 31).is_err());
+        assert!(check_pyramid(0, 1).is_err());
+        assert!(check_pyramid(1, 32).is_err());
+        assert!(check_pyramid(256, 24).is_err());
+    }
+
+    #[test]
+    fn test_export() -> Result<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let m = temp_database(2, &[("a", 4), ("ac", 2)]);
+        assert_eq!(vec![0, 4, 2], ring_maxes(&m, 2)?);
+        export(&m, Sector::full(2), TileFormat::Xyz, 8, 1, dir)?;
+        let tile = image::open(dir.join("1/1/0.png")).unwrap();
+        assert_eq!((8, 8), (tile.width(), tile.height()));
+        assert!(dir.join("0/0/0.png").exists());
+        Ok(())
+    }
+}
@@ -1,7 +1,13 @@
-// traverse helps to iterate the mmap in a minimally querying way
-// to populate a desired set of pixel points.
-
-use core::{f64::consts::PI, num};
+//! traverse helps to iterate the mmap in a minimally querying way
+//! to populate a desired set of pixel points.
+
+use core::{
+    f64::consts::{PI, SQRT_2},
+    num,
+};
+use crate::alphabet::{self, Alphabet};
+use crate::error::{Error, Result};
+use crate::kmer::check_index_len;
 use itertools::Itertools;
 
 pub fn filter_points(num_workers: usize, worker_id: usize, point: &Point) -> bool {
@@ -11,31 +17,9 @@ pub fn filter_points(num_workers: usize, worker_id: usize, point: &Point) -> boo
     worker_id == work % num_workers
 }
 
-pub fn point_chunk_id(num_chunks: usize, point: &Point) -> usize {
-  let (theta, _) = point.thetas();
-  let percentage = (theta / (2.0 * PI)).max(0.0).min(1.0);
-  let chunk_id = (percentage * num_chunks as f64) as usize % num_chunks;
-  chunk_id
-}
-
-fn bits_to_seq(bits: u64, bitsize: usize) -> String {
-    let mut bits = bits;
-    let mut seq = "".to_string();
-    for _ in 0..bitsize {
-        let tail = bits & 0b11;
-        bits = bits >> 2;
-        let s = match tail {
-            0b00 => "a",
-            0b01 => "c",
-            0b10 => "g",
-            0b11 => "t",
-            _ => panic!("impossible tail"),
-        };
-        seq.push_str(s);
-    }
-    seq.chars().rev().collect::<String>()
-}
-
+/// Point is a pixel of the radial star. x and y are relative to the center of
+/// the image, w and h are the pixel's column and row, and the pixel covers a
+/// contiguous index range of the ring holding seqlen.
 #[derive(Debug, Clone, Copy, Default)]
 pub struct Point {
     pub x: i32,
@@ -43,6 +27,119 @@ pub struct Point {
     pub w: u32,
     pub h: u32,
     pub seqlen: usize,
+    pub sector: Sector,
+}
+
+/// Sector selects the part of the star that is stretched over an image. gte and
+/// lt are the [gte, lt) fraction of the full circle and rings run from min_seqlen
+/// to max_seqlen. Fractions are kept instead of angles so that sectors built from
+/// a prefix land exactly on index boundaries. radix is the number of symbols of
+/// the alphabet of the index, which splits every ring radix times more finely
+/// than the one inside it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sector {
+    pub gte: f64,
+    pub lt: f64,
+    pub min_seqlen: usize,
+    pub max_seqlen: usize,
+    pub radix: usize,
+}
+
+impl Default for Sector {
+    fn default() -> Self {
+        Sector::full(1)
+    }
+}
+
+fn check_min_seqlen(min_seqlen: usize, max_seqlen: usize) -> Result<()> {
+    if min_seqlen < 1 || min_seqlen > max_seqlen {
+        return Err(Error::InvalidSector(format!(
+            "min_seqlen={} must be within 1..={}",
+            min_seqlen, max_seqlen
+        )));
+    }
+    Ok(())
+}
+
+impl Sector {
+    pub fn full(max_seqlen: usize) -> Sector {
+        Sector {
+            gte: 0.0,
+            lt: 1.0,
+            min_seqlen: 1,
+            max_seqlen,
+            radix: 4,
+        }
+    }
+
+    pub fn from_thetas(
+        theta_gte: f64,
+        theta_lt: f64,
+        min_seqlen: usize,
+        max_seqlen: usize,
+    ) -> Result<Sector> {
+        if !(0.0 <= theta_gte && theta_gte < theta_lt && theta_lt <= 2.0 * PI) {
+            return Err(Error::InvalidSector(format!(
+                "theta range [{}, {}) is not within [0, 2PI)",
+                theta_gte, theta_lt
+            )));
+        }
+        check_index_len(max_seqlen)?;
+        check_min_seqlen(min_seqlen, max_seqlen)?;
+        Ok(Sector {
+            gte: theta_gte / (2.0 * PI),
+            lt: theta_lt / (2.0 * PI),
+            min_seqlen,
+            max_seqlen,
+            radix: 4,
+        })
+    }
+
+    // with_alphabet lays the sector out for an index over alphabet.
+    pub fn with_alphabet(self, alphabet: &Alphabet) -> Result<Sector> {
+        alphabet.check_index_len(self.max_seqlen)?;
+        Ok(Sector {
+            radix: alphabet.radix(),
+            ..self
+        })
+    }
+
+    // with_min_seqlen starts the rings of the sector at min_seqlen instead.
+    pub fn with_min_seqlen(self, min_seqlen: usize) -> Result<Sector> {
+        check_min_seqlen(min_seqlen, self.max_seqlen)?;
+        Ok(Sector { min_seqlen, ..self })
+    }
+
+    // from_prefix returns the sector holding every sequence that starts with
+    // prefix, from the ring of the prefix itself out to max_seqlen.
+    pub fn from_prefix(alphabet: &Alphabet, prefix: &str, max_seqlen: usize) -> Result<Sector> {
+        if prefix.is_empty() || prefix.len() > max_seqlen {
+            return Err(Error::InvalidSector(format!(
+                "prefix {:?} must be 1..={} long",
+                prefix, max_seqlen
+            )));
+        }
+        alphabet.check_index_len(max_seqlen)?;
+        let max = alphabet.ring_len(prefix.len()) as f64;
+        let addr = (alphabet.seq_to_index(prefix)? - alphabet.base_index(prefix.len())) as f64;
+        Ok(Sector {
+            gte: addr / max,
+            lt: (addr + 1.0) / max,
+            min_seqlen: prefix.len(),
+            max_seqlen,
+            radix: alphabet.radix(),
+        })
+    }
+
+    // seqlen maps the squared radius fraction p in [0, 1] onto a ring.
+    fn seqlen(&self, p: f64) -> usize {
+        (p * (self.max_seqlen - self.min_seqlen) as f64) as usize + self.min_seqlen
+    }
+
+    // fraction maps the fraction t of the full circle onto the sector.
+    fn fraction(&self, t: f64) -> f64 {
+        self.gte + t * (self.lt - self.gte)
+    }
 }
 
 fn theta_delta(t1: f64, t2: f64) -> (f64, f64) {
@@ -63,25 +160,67 @@ fn theta_delta(t1: f64, t2: f64) -> (f64, f64) {
     }
 }
 
-pub fn make_points(width: u32, height: u32, max_length: u32) -> Vec<Point> {
-    let mut points: Vec<Point> = Vec::with_capacity(width as usize * height as usize);
+// index_theta is the inverse of Point::index and returns the angle at which the
+// sequence stored at index begins on its ring in an index of radix symbols.
+pub fn index_theta(radix: usize, seqlen: usize, index: usize) -> f64 {
+    let addr = index - alphabet::base_index(radix, seqlen);
+    addr as f64 / alphabet::ring_len(radix, seqlen) as f64 * 2.0 * PI
+}
+
+// ring_radii returns the [inner, outer) radius of the ring holding seqlen as a
+// fraction of the image edge, matching the radius to seqlen mapping that
+// make_points uses. The outermost ring extends to the corners of the image.
+pub fn ring_radii(seqlen: usize, max_length: usize) -> (f64, f64) {
+    if max_length <= 1 {
+        return (0.0, SQRT_2);
+    }
+    let steps = (max_length - 1) as f64;
+    let inner = ((seqlen - 1) as f64 / steps).sqrt();
+    let outer = if seqlen >= max_length {
+        SQRT_2
+    } else {
+        (seqlen as f64 / steps).sqrt()
+    };
+    (inner, outer)
+}
+
+pub fn make_points(width: u32, height: u32, sector: Sector) -> Vec<Point> {
+    make_window_points(width, height, sector, 0, 0, width, height)
+}
+
+// make_window_points creates only the points inside a window of a width x height
+// image, with w and h relative to the window's top left corner. This allows
+// rendering a zoomed in part of a very large image without visiting every pixel.
+// The image covers the given sector of the star.
+pub fn make_window_points(
+    width: u32,
+    height: u32,
+    sector: Sector,
+    left: u32,
+    top: u32,
+    window_width: u32,
+    window_height: u32,
+) -> Vec<Point> {
+    let mut points: Vec<Point> =
+        Vec::with_capacity(window_width as usize * window_height as usize);
     let edge = width.max(height);
     let edge = edge / 2;
-    let edge_squared = edge * edge;
-    for w in 0..width {
-        for h in 0..height {
-            let x = width as i32 / 2 - w as i32;
-            let y = height as i32 / 2 - h as i32;
-            let r_squared = x * x + y * y;
+    let edge_squared = edge as i64 * edge as i64;
+    for w in 0..window_width {
+        for h in 0..window_height {
+            let x = width as i32 / 2 - (left + w) as i32;
+            let y = height as i32 / 2 - (top + h) as i32;
+            let r_squared = x as i64 * x as i64 + y as i64 * y as i64;
             let p = r_squared as f64 / edge_squared as f64;
             let p = p.min(1.0);
-            let seqlen = (p * (max_length - 1) as f64) as usize + 1;
+            let seqlen = sector.seqlen(p);
             points.push(Point {
                 x: x,
                 y: y,
                 w,
                 h,
                 seqlen,
+                sector,
             });
         }
     }
@@ -99,6 +238,24 @@ pub fn make_points(width: u32, height: u32, max_length: u32) -> Vec<Point> {
     points
 }
 
+// make_supersampled_points splits every pixel into samples x samples sub
+// pixels. Every point keeps the w and h of the pixel it falls into, so pixels on
+// a ring or wedge boundary get points of both sides.
+pub fn make_supersampled_points(
+    width: u32,
+    height: u32,
+    sector: Sector,
+    samples: u32,
+) -> Vec<Point> {
+    let (sw, sh) = (width * samples, height * samples);
+    let mut points = make_window_points(sw, sh, sector, 0, 0, sw, sh);
+    for p in points.iter_mut() {
+        p.w /= samples;
+        p.h /= samples;
+    }
+    points
+}
+
 impl Point {
     fn thetas(&self) -> (f64, f64) {
         let (x, y) = (self.x, self.y);
@@ -161,9 +318,9 @@ impl Point {
     }
 
     fn index(&self, theta: f64) -> usize {
-        let base: usize = (1..self.seqlen).fold(0, |acc, i| acc + (1 << (2 * i)));
-        let max: usize = 1 << self.seqlen * 2;
-        let percentage = theta / (2.0 * PI);
+        let base = alphabet::base_index(self.sector.radix, self.seqlen);
+        let max = alphabet::ring_len(self.sector.radix, self.seqlen);
+        let percentage = self.sector.fraction(theta / (2.0 * PI));
         let addr = max as f64 * percentage;
         let addr = addr as usize;
         base + addr
@@ -257,6 +414,93 @@ mod test {
         assert_eq!((6.220276301144461, 6.228552227940226), p.thetas());
     }
 
+    #[test]
+    fn test_index_theta() {
+        assert_eq!(0.0, index_theta(4, 1, 0));
+        assert_eq!(PI, index_theta(4, 1, 2));
+        assert_eq!(PI / 2.0, index_theta(4, 2, 8));
+        assert_eq!(PI, index_theta(20, 1, 10));
+        let p = Point {
+            x: -1,
+            y: 0,
+            seqlen: 3,
+            ..Point::default()
+        };
+        let (gte, _) = p.index_range();
+        assert_eq!(PI / 2.0, index_theta(4, 3, gte));
+    }
+
+    #[test]
+    fn test_ring_radii() {
+        assert_eq!((0.0, SQRT_2), ring_radii(1, 1));
+        assert_eq!((0.0, 0.5_f64.sqrt()), ring_radii(1, 3));
+        assert_eq!((0.5_f64.sqrt(), 1.0), ring_radii(2, 3));
+        assert_eq!((1.0, SQRT_2), ring_radii(3, 3));
+    }
+
+    #[test]
+    fn test_sector() -> Result<()> {
+        let sector = Sector::from_prefix(&Alphabet::dna(), "cg", 4)?;
+        assert_eq!((6.0 / 16.0, 7.0 / 16.0, 2), (sector.gte, sector.lt, 2));
+        let points = make_window_points(64, 64, sector, 0, 0, 64, 64);
+        for p in &points {
+            assert!(p.seqlen >= 2 && p.seqlen <= 4);
+            let (gte, lt) = p.index_range();
+            let base = Alphabet::dna().base_index(p.seqlen);
+            let shift = 2 * (p.seqlen - 2);
+            assert!(gte >= base + (6 << shift), "{:?}", p);
+            assert!(lt <= base + (7 << shift), "{:?}", p);
+            assert!(p.seq_range().0.starts_with("cg"), "{:?}", p);
+        }
+        assert!(Sector::from_prefix(&Alphabet::dna(), "cn", 4).is_err());
+        assert!(Sector::from_prefix(&Alphabet::dna(), "acgta", 4).is_err());
+        assert_eq!(3, sector.with_min_seqlen(3)?.min_seqlen);
+        assert!(sector.with_min_seqlen(5).is_err());
+        assert!(sector.with_min_seqlen(0).is_err());
+
+        let sector = Sector::from_thetas(PI, 2.0 * PI, 3, 3)?;
+        let p = Point {
+            x: 0,
+            y: 0,
+            seqlen: 3,
+            sector,
+            ..Point::default()
+        };
+        assert_eq!(("gaa".to_string(), "gga".to_string()), p.seq_range());
+        assert!(Sector::from_thetas(PI, 0.0, 1, 3).is_err());
+        assert!(Sector::from_thetas(0.0, PI, 4, 3).is_err());
+        assert!(Sector::from_thetas(0.0, PI, 1, 40).is_err());
+
+        let protein = Alphabet::protein();
+        let sector = Sector::from_prefix(&protein, "w", 2)?;
+        assert_eq!((18.0 / 20.0, 19.0 / 20.0), (sector.gte, sector.lt));
+        let p = Point {
+            x: 0,
+            y: 0,
+            seqlen: 2,
+            sector,
+            ..Point::default()
+        };
+        assert_eq!((380, 385), p.index_range());
+        assert_eq!("wa", protein.index_to_seq(p.index_range().0));
+        assert_eq!(20, Sector::full(3).with_alphabet(&protein)?.radix);
+        assert!(Sector::full(20).with_alphabet(&protein).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_make_supersampled_points() {
+        let points = make_supersampled_points(16, 16, Sector::full(3), 4);
+        assert_eq!(16 * 16 * 16, points.len());
+        assert!(points.iter().all(|p| p.w < 16 && p.h < 16));
+        // pixels on a ring boundary hold samples of both rings.
+        let mut seqlens = vec![Vec::new(); 16 * 16];
+        for p in &points {
+            seqlens[(p.h * 16 + p.w) as usize].push(p.seqlen);
+        }
+        assert!(seqlens.iter().any(|s| s.contains(&2) && s.contains(&3)));
+    }
+
     #[test]
     fn test_modulus() {
         assert_eq!(PI, (-PI + 2.0 * PI) % (2.0 * PI));
@@ -269,7 +513,7 @@ mod test {
 
     #[test]
     fn test_make_points() {
-        let points = make_points(50, 50, 8);
+        let points = make_points(50, 50, Sector::full(8));
         for p in &points {
             println!("point={:?} range={:?}", p, p.index_range());
         }
@@ -0,0 +1,212 @@
+// view keeps the state of an interactive, zoomable window onto the star. The
+// visible square is a window into a virtual image that is zoom times larger than
+// the window, so zooming in re-renders a smaller angular sector at full
+// resolution instead of scaling up pixels.
+
+use crate::database::Database;
+use crate::error::{image_error, Result};
+use crate::layout::Pixel;
+use crate::render::{count_pixels, density_image, ring_maxes, Counts};
+use crate::traverse::{make_window_points, Point, Sector};
+use image::GrayImage;
+use tracing::info;
+
+// MAX_ZOOM keeps the virtual image coordinates within an i32.
+const MAX_ZOOM: u32 = 1 << 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct View {
+    pub side_length: u32,
+    pub max_length: u32,
+    pub zoom: u32,
+    pub left: u32,
+    pub top: u32,
+}
+
+impl View {
+    pub fn new(side_length: u32, max_length: u32) -> View {
+        View {
+            side_length,
+            max_length,
+            zoom: 1,
+            left: 0,
+            top: 0,
+        }
+    }
+
+    // zoomed moves the view to the given zoom and offset into the virtual image.
+    pub fn zoomed(mut self, zoom: u32, left: u32, top: u32) -> View {
+        self.zoom = zoom.clamp(1, MAX_ZOOM);
+        self.left = left;
+        self.top = top;
+        self.clamp();
+        self
+    }
+
+    fn virtual_side(&self) -> u32 {
+        self.side_length * self.zoom
+    }
+
+    fn clamp(&mut self) {
+        let max = self.virtual_side() - self.side_length;
+        self.left = self.left.min(max);
+        self.top = self.top.min(max);
+    }
+
+    // zoom_in doubles the zoom while keeping the pixel under (w, h) in place.
+    pub fn zoom_in(&mut self, w: u32, h: u32) {
+        if self.zoom >= MAX_ZOOM {
+            return;
+        }
+        self.zoom *= 2;
+        self.left = (self.left + w) * 2 - w;
+        self.top = (self.top + h) * 2 - h;
+        self.clamp();
+    }
+
+    // zoom_out halves the zoom while keeping the pixel under (w, h) in place.
+    pub fn zoom_out(&mut self, w: u32, h: u32) {
+        if self.zoom <= 1 {
+            return;
+        }
+        self.zoom /= 2;
+        self.left = ((self.left + w) / 2).saturating_sub(w);
+        self.top = ((self.top + h) / 2).saturating_sub(h);
+        self.clamp();
+    }
+
+    pub fn pan(&mut self, dw: i64, dh: i64) {
+        self.left = (self.left as i64 + dw).max(0) as u32;
+        self.top = (self.top as i64 + dh).max(0) as u32;
+        self.clamp();
+    }
+
+    pub fn points(&self) -> Vec<Point> {
+        let side = self.virtual_side();
+        make_window_points(
+            side,
+            side,
+            Sector::full(self.max_length as usize),
+            self.left,
+            self.top,
+            self.side_length,
+            self.side_length,
+        )
+    }
+
+    // point_at returns the point under the window pixel (w, h).
+    pub fn point_at(&self, w: u32, h: u32) -> Point {
+        let side = self.virtual_side();
+        let mut points = make_window_points(
+            side,
+            side,
+            Sector::full(self.max_length as usize),
+            self.left + w,
+            self.top + h,
+            1,
+            1,
+        );
+        let mut p = points.remove(0);
+        p.w = w;
+        p.h = h;
+        p
+    }
+
+    // render counts the view and draws it with every ring scaled by its
+    // largest count, so views that miss the outer ring are not blank.
+    pub fn render(&self, m: &Database, ring_maxes: &[u32]) -> (Counts, GrayImage) {
+        let side = self.side_length as usize;
+        let pixels: Vec<Pixel> = self.points().iter().map(Pixel::from).collect();
+        let counts = count_pixels(m, pixels.clone(), side, side, self.max_length as usize);
+        let image = density_image(&pixels, &counts, ring_maxes);
+        (counts, image)
+    }
+}
+
+// describe explains the pixel (w, h) of a rendered view.
+pub fn describe(view: &View, counts: &Counts, w: u32, h: u32) -> String {
+    let p = view.point_at(w, h);
+    let (from, to) = p.seq_range();
+    format!(
+        "seqlen={} seq_range={}..{} count={}",
+        p.seqlen,
+        from,
+        to,
+        counts.get(w as usize, h as usize)
+    )
+}
+
+// dump renders the view and writes it as an image without opening a window.
+pub fn dump(m: &Database, view: &View, path: &str) -> Result<()> {
+    m.alphabet().check_dna("viewer")?;
+    m.check_seqlen(view.max_length as usize)?;
+    let ring_maxes = ring_maxes(m, view.max_length as usize)?;
+    let (_, image) = view.render(m, &ring_maxes);
+    image.save(path).map_err(image_error(path))?;
+    info!("wrote view {:?} to {}", view, path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_zoom() {
+        let mut v = View::new(100, 4);
+        let before = v.point_at(30, 60);
+        v.zoom_in(30, 60);
+        assert_eq!((2, 30, 60), (v.zoom, v.left, v.top));
+        let after = v.point_at(30, 60);
+        assert_eq!(before.seqlen, after.seqlen);
+        v.zoom_out(30, 60);
+        assert_eq!(View::new(100, 4), v);
+        v.zoom_out(30, 60);
+        assert_eq!(View::new(100, 4), v);
+    }
+
+    #[test]
+    fn test_zoomed() {
+        let v = View::new(100, 4).zoomed(4, 250, 1000);
+        assert_eq!((4, 250, 300), (v.zoom, v.left, v.top));
+        let v = View::new(100, 4).zoomed(0, 5, 5);
+        assert_eq!((1, 0, 0), (v.zoom, v.left, v.top));
+    }
+
+    #[test]
+    fn test_pan() {
+        let mut v = View::new(100, 4);
+        v.pan(10, 10);
+        assert_eq!((0, 0), (v.left, v.top));
+        v.zoom_in(0, 0);
+        v.pan(-10, 30);
+        assert_eq!((0, 30), (v.left, v.top));
+        v.pan(500, 500);
+        assert_eq!((100, 100), (v.left, v.top));
+    }
+
+    #[test]
+    fn test_points() {
+        let mut v = View::new(10, 4);
+        v.zoom_in(0, 0);
+        v.pan(3, 4);
+        let points = v.points();
+        assert_eq!(100, points.len());
+        let p = points.iter().find(|p| p.w == 2 && p.h == 1).unwrap();
+        let q = v.point_at(2, 1);
+        assert_eq!((p.x, p.y, p.seqlen), (q.x, q.y, q.seqlen));
+        assert_eq!((10 - 5, 10 - 5), (q.x, q.y));
+    }
+
+    #[test]
+    fn test_render() -> Result<()> {
+        let m = crate::database::temp_database(2, &[("a", 16), ("c", 1), ("ag", 1)]);
+        // the window only covers the inner ring, far from the image corners.
+        let v = View::new(16, 2).zoomed(4, 24, 24);
+        let (_, image) = v.render(&m, &ring_maxes(&m, 2)?);
+        let pixels: Vec<u8> = image.pixels().map(|p| p.0[0]).collect();
+        assert!(pixels.contains(&255));
+        assert!(pixels.contains(&127));
+        Ok(())
+    }
+}